@@ -18,11 +18,19 @@ car_secret = open(os.path.join(secrets_dir, "car_sec"), "rb").read()
 man_public = open(os.path.join(secrets_dir, "man_pub"), "rb").read()
 fob_public = open(os.path.join(secrets_dir, "fob_pub"), "rb").read()
 car_id = int(sys.argv[3]).to_bytes(4, "big")
+unlock_fails = b"\x00\x00\x00\x00" # No bad unlock responses yet
+chal_counter = b"\x00\x00\x00\x00" # No challenges handed out yet
+rtt_limit = b"\xff\xff\xff\xff" # Not calibrated, the fob's round trip is not timed
+rng_seed = os.urandom(32) # Mixed into the RNG on first boot, then replaced
 
 addresses = {
     "CARMEM_CAR_SECRET":     [0x100, car_secret],
     "CARMEM_MAN_PUBLIC":     [0x120, man_public],
     "CARMEM_FOB_PUBLIC":     [0x160, fob_public],
+    "CARMEM_UNLOCK_FAILS":   [0x1A0, unlock_fails],
+    "CARMEM_CHAL_COUNTER":   [0x1A4, chal_counter],
+    "CARMEM_RTT_LIMIT":      [0x1A8, rtt_limit],
+    "CARMEM_RNG_SEED":       [0x1E0, rng_seed],
     "CARMEM_CAR_ID":         [0x200, car_id],
     "CARMEM_MSG_FEAT_3":     [0x700, None],
     "CARMEM_MSG_FEAT_2":     [0x740, None],
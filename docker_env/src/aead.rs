@@ -0,0 +1,232 @@
+//! ChaCha20-Poly1305 authenticated encryption (RFC 8439). Used to keep
+//! secrets sent between boards away from anyone listening on the link, and
+//! to catch any change made to them on the way.
+
+use crate::ct_eq;
+
+pub const LEN_KEY:                usize = 32;
+pub const LEN_AEAD_NONCE:         usize = 12;
+pub const LEN_TAG:                usize = 16;
+
+/// "expand 32-byte k"
+const SIGMA: [u32; 4] = [0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574];
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// One 64 byte block of ChaCha20 key stream.
+pub fn chacha20_block(key: &[u8; LEN_KEY], counter: u32, nonce: &[u8; LEN_AEAD_NONCE]) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&SIGMA);
+    for i in 0..8 {
+        state[4 + i] = le32(&key[i * 4..]);
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = le32(&nonce[i * 4..]);
+    }
+
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+
+    let mut block = [0u8; 64];
+    for i in 0..16 {
+        block[i * 4..i * 4 + 4].copy_from_slice(&working[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    block
+}
+
+/// Encrypts or decrypts `data` in place with ChaCha20, starting at block
+/// `counter`.
+pub fn chacha20_xor(key: &[u8; LEN_KEY], counter: u32, nonce: &[u8; LEN_AEAD_NONCE], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (byte, key_byte) in chunk.iter_mut().zip(block) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+/// The Poly1305 one-time authenticator, fed a piece at a time. Works on 26
+/// bit limbs so every product fits in a u64.
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buf: [u8; 16],
+    len: usize,
+}
+
+impl Poly1305 {
+    pub fn new(key: &[u8; 32]) -> Poly1305 {
+        Poly1305 {
+            // r is clamped as the RFC asks
+            r: [
+                le32(&key[0..]) & 0x03FF_FFFF,
+                (le32(&key[3..]) >> 2) & 0x03FF_FF03,
+                (le32(&key[6..]) >> 4) & 0x03FF_C0FF,
+                (le32(&key[9..]) >> 6) & 0x03F0_3FFF,
+                (le32(&key[12..]) >> 8) & 0x000F_FFFF,
+            ],
+            h: [0; 5],
+            pad: [le32(&key[16..]), le32(&key[20..]), le32(&key[24..]), le32(&key[28..])],
+            buf: [0; 16],
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = (16 - self.len).min(data.len());
+            self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+            if self.len == 16 {
+                let block = self.buf;
+                self.block(&block, 1 << 24);
+                self.len = 0;
+            }
+        }
+    }
+
+    /// Fills a partial block with zeros, as the AEAD construction does
+    /// between the associated data and the ciphertext.
+    pub fn pad16(&mut self) {
+        if self.len > 0 {
+            self.update(&[0; 16][self.len..]);
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; LEN_TAG] {
+        if self.len > 0 {
+            let mut block = [0u8; 16];
+            block[..self.len].copy_from_slice(&self.buf[..self.len]);
+            block[self.len] = 1;
+            self.block(&block, 0);
+        }
+
+        // Carry everything through
+        let [mut h0, mut h1, mut h2, mut h3, mut h4] = self.h;
+        let mut c;
+        c = h1 >> 26; h1 &= 0x03FF_FFFF; h2 += c;
+        c = h2 >> 26; h2 &= 0x03FF_FFFF; h3 += c;
+        c = h3 >> 26; h3 &= 0x03FF_FFFF; h4 += c;
+        c = h4 >> 26; h4 &= 0x03FF_FFFF; h0 += c * 5;
+        c = h0 >> 26; h0 &= 0x03FF_FFFF; h1 += c;
+
+        // g = h - p, used instead of h if it did not go negative
+        let mut g0 = h0.wrapping_add(5);
+        c = g0 >> 26; g0 &= 0x03FF_FFFF;
+        let mut g1 = h1.wrapping_add(c);
+        c = g1 >> 26; g1 &= 0x03FF_FFFF;
+        let mut g2 = h2.wrapping_add(c);
+        c = g2 >> 26; g2 &= 0x03FF_FFFF;
+        let mut g3 = h3.wrapping_add(c);
+        c = g3 >> 26; g3 &= 0x03FF_FFFF;
+        let g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+
+        let mask = (g4 >> 31).wrapping_sub(1);
+        h0 = (h0 & !mask) | (g0 & mask);
+        h1 = (h1 & !mask) | (g1 & mask);
+        h2 = (h2 & !mask) | (g2 & mask);
+        h3 = (h3 & !mask) | (g3 & mask);
+        h4 = (h4 & !mask) | (g4 & mask);
+
+        // Back to 32 bit words, then add the pad mod 2^128
+        let words = [
+            h0 | (h1 << 26),
+            (h1 >> 6) | (h2 << 20),
+            (h2 >> 12) | (h3 << 14),
+            (h3 >> 18) | (h4 << 8),
+        ];
+        let mut tag = [0u8; LEN_TAG];
+        let mut carry = 0u64;
+        for i in 0..4 {
+            let sum = words[i] as u64 + self.pad[i] as u64 + carry;
+            tag[i * 4..i * 4 + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+            carry = sum >> 32;
+        }
+        tag
+    }
+
+    /// h = (h + block) * r mod 2^130 - 5
+    fn block(&mut self, block: &[u8; 16], hibit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(|r| r as u64);
+        let [s1, s2, s3, s4] = [r1 * 5, r2 * 5, r3 * 5, r4 * 5];
+
+        let h0 = (self.h[0] + (le32(&block[0..]) & 0x03FF_FFFF)) as u64;
+        let h1 = (self.h[1] + ((le32(&block[3..]) >> 2) & 0x03FF_FFFF)) as u64;
+        let h2 = (self.h[2] + ((le32(&block[6..]) >> 4) & 0x03FF_FFFF)) as u64;
+        let h3 = (self.h[3] + ((le32(&block[9..]) >> 6) & 0x03FF_FFFF)) as u64;
+        let h4 = (self.h[4] + ((le32(&block[12..]) >> 8) | hibit)) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        d2 += d1 >> 26;
+        d3 += d2 >> 26;
+        d4 += d3 >> 26;
+        let h0 = (d0 & 0x03FF_FFFF) + (d4 >> 26) * 5;
+        let h1 = (d1 & 0x03FF_FFFF) + (h0 >> 26);
+        self.h = [h0, h1, d2, d3, d4].map(|d| d as u32);
+        self.h[0] &= 0x03FF_FFFF;
+        self.h[2] &= 0x03FF_FFFF;
+        self.h[3] &= 0x03FF_FFFF;
+        self.h[4] &= 0x03FF_FFFF;
+    }
+}
+
+/// The Poly1305 tag over associated data and ciphertext.
+fn aead_tag(key: &[u8; LEN_KEY], nonce: &[u8; LEN_AEAD_NONCE], aad: &[u8], ciphertext: &[u8]) -> [u8; LEN_TAG] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut mac = Poly1305::new(block[..32].try_into().unwrap());
+    mac.update(aad);
+    mac.pad16();
+    mac.update(ciphertext);
+    mac.pad16();
+    mac.update(&(aad.len() as u64).to_le_bytes());
+    mac.update(&(ciphertext.len() as u64).to_le_bytes());
+    mac.finish()
+}
+
+/// Encrypts `data` in place and returns the tag that covers it and `aad`.
+/// A key must never be used twice with the same nonce.
+pub fn seal(key: &[u8; LEN_KEY], nonce: &[u8; LEN_AEAD_NONCE], aad: &[u8], data: &mut [u8]) -> [u8; LEN_TAG] {
+    chacha20_xor(key, 1, nonce, data);
+    aead_tag(key, nonce, aad, data)
+}
+
+/// Checks the tag and decrypts `data` in place. Returns false, leaving
+/// `data` encrypted, if the tag does not match.
+pub fn open(key: &[u8; LEN_KEY], nonce: &[u8; LEN_AEAD_NONCE], aad: &[u8], data: &mut [u8], tag: &[u8; LEN_TAG]) -> bool {
+    if !ct_eq(&aead_tag(key, nonce, aad, data), tag) {
+        return false;
+    }
+    chacha20_xor(key, 1, nonce, data);
+    true
+}
@@ -0,0 +1,128 @@
+//! Runs the car or fob firmware on a computer. The host UART and the board
+//! link are TCP sockets and the EEPROM is an image file from the provisioning
+//! scripts, so the host tools can talk to a simulated car or fob just like
+//! they talk to a real one.
+//!
+//! Usage:
+//!   sim car --eeprom car.eeprom --host-port 50000 --link-listen 50001
+//!   sim fob --eeprom fob.eeprom --host-port 50002 --link-connect 127.0.0.1:50001
+//!
+//! For a fob, pressing enter presses SW1.
+
+use std::io::BufRead;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tiva::{
+  car::Car,
+  fob::Fob,
+  host::{FileStorage, TcpPort, WallClock},
+  rng::HmacDrbg,
+  sha256, EntropyPool, Led, Leds
+};
+
+const USAGE: &str = "usage: sim car|fob --eeprom PATH --host-port PORT \
+                     (--link-listen PORT | --link-connect ADDR)";
+
+/// LEDs that print their changes to stderr.
+struct ConsoleLeds;
+
+impl Leds for ConsoleLeds {
+  fn set_led(&mut self, led: Led, on: bool) {
+    eprintln!("LED {:?} {}", led, if on { "on" } else { "off" });
+  }
+}
+
+/// Stands in for the board entropy sources: the time, the process ID and a
+/// count of calls, hashed.
+fn sim_entropy(_pool: &mut EntropyPool) -> [u8; 32] {
+  static CALLS: AtomicU32 = AtomicU32::new(0);
+  let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+  let mut seed = [0u8; 24];
+  seed[..16].copy_from_slice(&time.to_le_bytes());
+  seed[16..20].copy_from_slice(&process::id().to_le_bytes());
+  seed[20..].copy_from_slice(&CALLS.fetch_add(1, Ordering::SeqCst).to_le_bytes());
+  sha256(&seed)
+}
+
+struct Args {
+  device: String,
+  eeprom: String,
+  host_port: u16,
+  link_listen: Option<u16>,
+  link_connect: Option<String>,
+}
+
+fn parse_args() -> Option<Args> {
+  let mut args = std::env::args().skip(1);
+  let device = args.next()?;
+  let mut eeprom = None;
+  let mut host_port = None;
+  let mut link_listen = None;
+  let mut link_connect = None;
+  while let Some(flag) = args.next() {
+    let value = args.next()?;
+    match flag.as_str() {
+      "--eeprom" => eeprom = Some(value),
+      "--host-port" => host_port = Some(value.parse().ok()?),
+      "--link-listen" => link_listen = Some(value.parse().ok()?),
+      "--link-connect" => link_connect = Some(value),
+      _ => return None,
+    }
+  }
+  if link_listen.is_some() == link_connect.is_some() {
+    return None;
+  }
+  Some(Args { device, eeprom: eeprom?, host_port: host_port?, link_listen, link_connect })
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+  eprintln!("{}", message);
+  process::exit(1);
+}
+
+fn main() {
+  let args = parse_args().unwrap_or_else(|| fail(USAGE));
+
+  let storage = FileStorage::open(&args.eeprom)
+    .unwrap_or_else(|e| fail(format!("failed to open {}: {}", args.eeprom, e)));
+  let host = TcpPort::listen(("0.0.0.0", args.host_port))
+    .unwrap_or_else(|e| fail(format!("failed to listen on host port: {}", e)));
+  let board_link = match (args.link_listen, &args.link_connect) {
+    (Some(port), _) => TcpPort::listen(("0.0.0.0", port)),
+    (_, Some(addr)) => TcpPort::connect(addr.as_str()),
+    _ => unreachable!(),
+  }.unwrap_or_else(|e| fail(format!("failed to set up board link: {}", e)));
+
+  let rng = HmacDrbg::new(sim_entropy, format!("PwnyPARED {} rng", args.device).as_bytes());
+
+  match args.device.as_str() {
+    "car" => {
+      let mut car = Car::new(host, board_link, storage, WallClock::new(), ConsoleLeds, rng);
+      loop {
+        car.poll();
+        thread::sleep(Duration::from_millis(1));
+      }
+    }
+    "fob" => {
+      // Each line on stdin is a press of SW1
+      let pressed = Arc::new(AtomicBool::new(false));
+      let stdin_pressed = pressed.clone();
+      thread::spawn(move || {
+        for _ in std::io::stdin().lock().lines() {
+          stdin_pressed.store(true, Ordering::SeqCst);
+        }
+      });
+
+      let mut fob = Fob::new(host, board_link, storage, WallClock::new(), ConsoleLeds, rng);
+      loop {
+        fob.poll(pressed.swap(false, Ordering::SeqCst));
+        thread::sleep(Duration::from_millis(1));
+      }
+    }
+    _ => fail(USAGE),
+  }
+}
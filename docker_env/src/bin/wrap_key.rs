@@ -0,0 +1,72 @@
+//! Hashes a PIN and wraps a fob secret under it the same way the fob
+//! firmware does, for the provisioning scripts. Prints PIN_HASH_ITERS,
+//! PIN_HASH and FOB_SECRET_ENC in hex, one per line, so the scripts need no
+//! copy of the iteration count or the hash.
+//!
+//! Usage:
+//!   wrap_key --secret fob_sec --pin 123456 --salt SALT_HEX --nonce NONCE_HEX
+
+use std::process;
+
+use tiva::{
+  pin::{pin_hash, PIN_HASH_ITERATIONS},
+  wrap::wrap,
+};
+
+const USAGE: &str = "usage: wrap_key --secret PATH --pin HEX --salt HEX --nonce HEX";
+
+struct Args {
+  secret: [u8; 32],
+  pin: Vec<u8>,
+  salt: Vec<u8>,
+  nonce: [u8; 12],
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+  if !s.len().is_multiple_of(2) {
+    return None;
+  }
+  (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_args() -> Option<Args> {
+  let mut args = std::env::args().skip(1);
+  let mut secret = None;
+  let mut pin = None;
+  let mut salt = None;
+  let mut nonce = None;
+  while let Some(flag) = args.next() {
+    let value = args.next()?;
+    match flag.as_str() {
+      "--secret" => secret = Some(std::fs::read(&value).unwrap_or_else(|e| fail(format!("failed to read {}: {}", value, e)))),
+      "--pin" => pin = Some(parse_hex(&value)?),
+      "--salt" => salt = Some(parse_hex(&value)?),
+      "--nonce" => nonce = Some(parse_hex(&value)?),
+      _ => return None,
+    }
+  }
+  Some(Args {
+    secret: secret?.try_into().ok()?,
+    pin: pin?,
+    salt: salt?,
+    nonce: nonce?.try_into().ok()?,
+  })
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+  eprintln!("{}", message);
+  process::exit(1);
+}
+
+fn main() {
+  let args = parse_args().unwrap_or_else(|| fail(USAGE));
+  let keys = pin_hash(&args.pin, &args.salt, PIN_HASH_ITERATIONS);
+  let record = wrap(&args.secret, &keys.wrap_key, &args.nonce);
+  println!("{}", hex(&PIN_HASH_ITERATIONS.to_be_bytes()));
+  println!("{}", hex(&keys.check));
+  println!("{}", hex(&record));
+}
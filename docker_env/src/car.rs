@@ -0,0 +1,627 @@
+use p256_cortex_m4::{SecretKey, Signature, PublicKey};
+
+use crate::{
+  words_to_bytes, bytes_to_words, Signer, Verifier, Clock, Event, Led, Leds, Storage, Port, Transport,
+  link::Link,
+  protocol::{
+    chal_transcript, resp_transcript, Message, MsgReader,
+    LEN_CAR_ID, LEN_CHAL_COUNTER, LEN_FEAT_NUM, LEN_NONCE, LEN_PING, LEN_SIG, LEN_TICKS, NUM_FEATURES
+  },
+  rfc6979::LEN_HEDGE,
+  rng::{next_rng_seed, EventRng, LEN_RNG_SEED}
+};
+
+/**
+ * EEPROM state addresses (specifically for car)
+ */
+pub const CARMEM_CAR_SECRET:      u32 = 0x100;
+pub const CARMEM_MAN_PUBLIC:      u32 = 0x120;
+pub const CARMEM_FOB_PUBLIC:      u32 = 0x160;
+pub const CARMEM_UNLOCK_FAILS:    u32 = 0x1A0;
+pub const CARMEM_CHAL_COUNTER:    u32 = 0x1A4;
+pub const CARMEM_RTT_LIMIT:       u32 = 0x1A8;
+pub const CARMEM_RNG_SEED:        u32 = 0x1E0;
+pub const CARMEM_CAR_ID:          u32 = 0x200;
+
+pub const CARMEM_MSG_FEAT_3:      u32 = 0x700;
+pub const CARMEM_MSG_FEAT_2:      u32 = 0x740;
+pub const CARMEM_MSG_FEAT_1:      u32 = 0x780;
+pub const CARMEM_MSG_UNLOCK:      u32 = 0x7C0;
+
+
+/**
+ * EEPROM state lengths
+ */
+// in bytes (for sending over UART)
+const LEN_FOB_PUBLIC:         usize = 64;
+const LEN_CAR_SECRET:         usize = 32;
+const LEN_MAN_PUBLIC:         usize = 64;
+const LEN_FLAG:               usize = 64;
+const LEN_UNLOCK_FAILS:       usize = 4;
+
+// in words (for accesing EEPROM)
+const LENW_FOB_PUBLIC:        usize = LEN_FOB_PUBLIC / 4;
+const LENW_CAR_SECRET:        usize = LEN_CAR_SECRET / 4;
+const LENW_MAN_PUBLIC:        usize = LEN_MAN_PUBLIC / 4;
+const LENW_CAR_ID:            usize = LEN_CAR_ID / 4;
+const LENW_FLAG:              usize = LEN_FLAG / 4;
+const LENW_UNLOCK_FAILS:      usize = LEN_UNLOCK_FAILS / 4;
+const LENW_CHAL_COUNTER:      usize = LEN_CHAL_COUNTER / 4;
+const LENW_RTT_LIMIT:         usize = LEN_TICKS / 4;
+const LENW_RNG_SEED:          usize = LEN_RNG_SEED / 4;
+
+/**
+ * Challenge counter
+ */
+const CHAL_COUNTER_BLOCK:     u32 = 64; // challenges reserved in EEPROM at a time
+
+/**
+ * Round trip timing
+ */
+const RTT_UNCALIBRATED:       u32 = u32::MAX; // erased EEPROM, UNLOCK_PONG is not timed
+const RTT_SLACK_PERCENT:      u64 = 50; // calibrated limit is this much above the measured round trip
+
+/**
+ * Timing
+ */
+const US_UNLOCK:              u64 = 500_000; // every unlock takes this long
+const US_RESP_TIMEOUT:        u64 = 1_000_000; // UNLOCK_PONG and UNLOCK_RESP must arrive by now
+const US_FEAT_TIMEOUT:        u64 = 500_000; // UNLOCK_FEAT must arrive this soon after UNLOCK_GOOD
+const US_UNLOCK_REJECT:       u64 = 4_500_000; // a bad response holds UNLOCK_RST back this much longer
+const US_LOCKOUT_BASE:        u64 = 5_000_000; // lockout after the first bad response, doubles with every one after that
+const US_LOCKOUT_MAX:         u64 = 3_600_000_000;
+
+/// Where the car is in the unlock protocol.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CarState {
+  /// Waiting for UNLOCK_REQ
+  Idle,
+  /// Waiting for the link to go quiet before sending UNLOCK_PING, so time
+  /// spent queued behind other messages is not timed
+  SendingPing,
+  /// Sent UNLOCK_PING, timing how long UNLOCK_PONG takes
+  AwaitingPong,
+  /// Sent UNLOCK_CHAL, waiting for UNLOCK_RESP
+  AwaitingResp,
+  /// The fob signed the nonce, waiting for the unlock time to pass
+  Unlocking,
+  /// Sent UNLOCK_GOOD, waiting for UNLOCK_FEAT
+  AwaitingFeatures,
+  /// The fob did not sign the nonce or answered UNLOCK_PING too slowly,
+  /// waiting for the unlock time to pass before sending UNLOCK_RST
+  Rejecting,
+}
+
+/// The car firmware as a state machine. Everything it talks to is passed in,
+/// so the same code runs on the board (see bin/car.rs) and on a computer (see
+/// bin/sim.rs). Feed it events with `on_byte()` and `on_tick()`, or call
+/// `poll()` to read them from the UARTs and clock.
+pub struct Car<H, B, S, C, L, R> {
+  /// UART to the host computer
+  pub host: H,
+  /// UART to the fob
+  pub board_link: B,
+  /// Car EEPROM
+  pub storage: S,
+  /// Delay and tick timers
+  pub clock: C,
+  /// Status LEDs
+  pub leds: L,
+  rng: R,
+  state: CarState,
+  host_rx: MsgReader,
+  link: Link,
+  ping: [u8; LEN_PING],
+  pong: [u8; LEN_PING],
+  ping_ticks: u64,
+  rtt_ticks: u64,
+  calibrating: bool,
+  car_nonce: [u8; LEN_NONCE],
+  car_nonce_sig: [u8; LEN_SIG],
+  chal_counter: u32,
+  chal_reserved: u32,
+  deadline_us: u64,
+  timeout_us: u64,
+  locked_until_us: u64,
+}
+
+impl<H, B, S, C, L, R> Car<H, B, S, C, L, R>
+where
+  H: Transport,
+  B: Transport,
+  S: Storage,
+  C: Clock,
+  L: Leds,
+  R: EventRng,
+{
+  /// Sets up the car. `rng` should already be seeded with entropy. The car
+  /// cannot tell how long it was off, so a lockout from before a reset
+  /// starts over in full.
+  pub fn new(host: H, board_link: B, storage: S, clock: C, leds: L, rng: R) -> Self {
+    let mut car = Car {
+      host, board_link, storage, clock, leds, rng,
+      state: CarState::Idle,
+      host_rx: MsgReader::new(),
+      link: Link::new(),
+      ping: [0; LEN_PING],
+      pong: [0; LEN_PING],
+      ping_ticks: 0,
+      rtt_ticks: 0,
+      calibrating: false,
+      car_nonce: [0; LEN_NONCE],
+      car_nonce_sig: [0; LEN_SIG],
+      chal_counter: 0,
+      chal_reserved: 0,
+      deadline_us: 0,
+      timeout_us: 0,
+      locked_until_us: 0,
+    };
+    car.carry_rng_seed();
+    car.locked_until_us = car.clock.now_us() + lockout_us(car.unlock_fails());
+    // Start after everything handed out before the last reset
+    car.chal_counter = car.stored_chal_counter();
+    car.chal_reserved = car.chal_counter;
+    car
+  }
+
+  /// Current protocol state.
+  pub fn state(&self) -> CarState {
+    self.state
+  }
+
+  /// Runs one iteration of the main loop. Hands every byte the host and the
+  /// fob have sent to `on_byte()`, then checks the clock with `on_tick()`.
+  pub fn poll(&mut self) {
+    while self.host.avail() {
+      let byte: u8 = self.host.readb();
+      self.on_byte(Port::Host, byte);
+    }
+    while self.board_link.avail() {
+      let byte: u8 = self.board_link.readb();
+      self.on_byte(Port::BoardLink, byte);
+    }
+    self.on_tick();
+  }
+
+  /// Handles a byte received on one of the UARTs.
+  pub fn on_byte(&mut self, port: Port, byte: u8) {
+    let now_us = self.clock.now_us();
+    self.add_event(Event::UartByte(port));
+    // Messages from the host are bare, the board link has its own framing
+    let received = match port {
+      Port::Host => self.host_rx.push_at(byte, now_us).and_then(Result::ok),
+      Port::BoardLink => self.link.on_byte(byte, now_us, &mut self.board_link),
+    };
+    if let Some(msg) = received {
+      self.on_message(port, msg);
+    }
+  }
+
+  /// Handles a whole message from the host or the fob.
+  fn on_message(&mut self, port: Port, msg: Message) {
+    match (self.state, port, msg) {
+      (_, Port::Host, Message::Calibrate) => {
+        // Only a car that was never calibrated can be, so nobody can loosen
+        // the limit later
+        if self.rtt_limit() == RTT_UNCALIBRATED {
+          // log!("Car: Calibrating on the next unlock");
+          self.calibrating = true;
+        } else {
+          Message::HostFailure.send(&mut self.host);
+        }
+      }
+      (CarState::Idle, Port::BoardLink, Message::UnlockReq) if self.clock.now_us() < self.locked_until_us => {
+        // log!("Car: Locked out, refusing UNLOCK_REQ");
+        self.send_to_board(Message::UnlockRst);
+      }
+      (CarState::Idle, Port::BoardLink, Message::UnlockReq) => {
+        // log!("Car: Received UNLOCK_REQ");
+        self.leds.set_led(Led::Blue, true);
+        self.unlock_start();
+      }
+      (CarState::AwaitingPong, Port::BoardLink, Message::UnlockPong { pong }) => {
+        // Time the round trip first, before anything else slows it down
+        self.rtt_ticks = self.clock.get_tick_timer().saturating_sub(self.ping_ticks);
+        // log!("Car: Received UNLOCK_PONG after {} ticks", self.rtt_ticks);
+        self.pong = pong;
+        self.unlock_send_chal();
+      }
+      (CarState::AwaitingResp, Port::BoardLink, Message::UnlockResp { nonce, nonce_sig }) => {
+        // log!("Car: Received UNLOCK_RESP");
+        self.unlock_check_resp(&nonce, &nonce_sig);
+      }
+      (CarState::AwaitingFeatures, Port::BoardLink, Message::UnlockFeat { feature_sigs }) => {
+        // log!("Car: Received UNLOCK_FEAT data");
+        self.unlock_send_features(&feature_sigs);
+        self.leds.set_led(Led::Green, false);
+        self.state = CarState::Idle;
+      }
+      (CarState::SendingPing | CarState::AwaitingPong | CarState::AwaitingResp | CarState::AwaitingFeatures, Port::BoardLink, Message::UnlockRst) => {
+        // log!("Car: Received UNLOCK_RST");
+        self.unlock_abandon();
+      }
+      (_, _, _msg) => {
+        // log!("Car: Received unexpected message: {:?}", _msg);
+      }
+    }
+  }
+
+  /// Handles the passing of time. Finishes an unlock once its time is up,
+  /// and gives up on a fob that stopped answering.
+  pub fn on_tick(&mut self) {
+    let now_us = self.clock.now_us();
+    // Drop a host message that stopped halfway, and resend anything the fob
+    // has not acknowledged yet
+    self.host_rx.expire(now_us);
+    self.link.on_tick(now_us, &mut self.board_link);
+    match self.state {
+      CarState::SendingPing | CarState::AwaitingPong | CarState::AwaitingResp | CarState::AwaitingFeatures if now_us >= self.timeout_us => {
+        // log!("Car: Fob stopped answering");
+        self.link.reset();
+        self.send_to_board(Message::UnlockRst);
+        self.unlock_abandon();
+      }
+      CarState::SendingPing => self.unlock_send_ping(),
+      CarState::Unlocking if now_us >= self.deadline_us => {
+        // yay unlock ze car
+        // log!("Car: Unlocked!");
+        self.leds.set_led(Led::Blue, false);
+        self.leds.set_led(Led::Green, true);
+        // Send unlock EEPROM message to UART host
+        let mut unlock_msg_w: [u32; LENW_FLAG] = [0; LENW_FLAG];
+        let mut unlock_msg_b: [u8; LEN_FLAG] = [0; LEN_FLAG];
+        self.storage.read(&mut unlock_msg_w, CARMEM_MSG_UNLOCK);
+        words_to_bytes(&unlock_msg_w, &mut unlock_msg_b);
+        self.host.write(&unlock_msg_b);
+
+        // Send UNLOCK_GOOD, signaling that we want to receive features
+        // log!("Car: Sending UNLOCK_GOOD to fob");
+        self.send_to_board(Message::UnlockGood);
+        self.timeout_us = now_us + US_FEAT_TIMEOUT;
+        self.state = CarState::AwaitingFeatures;
+      }
+      CarState::Rejecting if now_us >= self.deadline_us => {
+        self.send_to_board(Message::UnlockRst);
+        self.leds.set_led(Led::Red, false);
+        self.state = CarState::Idle;
+      }
+      _ => {}
+    }
+  }
+
+  /// Adds something the car saw to the RNG's entropy pool, along with when
+  /// it came in.
+  fn add_event(&mut self, event: Event) {
+    let ticks = self.clock.get_tick_timer();
+    self.rng.add_event(event, ticks);
+  }
+
+  /// Send a message to the fob. If the queue is full the fob has stopped
+  /// answering, and the unlock timeouts take care of it.
+  fn send_to_board(&mut self, msg: Message) {
+    let _ = self.link.send(&msg, &mut self.board_link);
+  }
+
+  /// Handle UNLOCK_REQ
+  fn unlock_start(&mut self) {
+    // Every unlock takes 500ms, need time to rx from fob
+    let now_us = self.clock.now_us();
+    self.deadline_us = now_us + US_UNLOCK;
+    // Give up if the fob does not answer in time
+    self.timeout_us = now_us + US_RESP_TIMEOUT;
+
+    // Time how long the fob takes to answer a short ping. A relay between
+    // the fob and the car adds to this
+    self.rng.fill_bytes(&mut self.ping);
+    self.state = CarState::SendingPing;
+    self.unlock_send_ping();
+  }
+
+  /// Sends UNLOCK_PING and starts timing it, unless the link is still busy
+  /// with an earlier message. The chunks would go out only after that one is
+  /// acknowledged, and the wait would count towards the round trip.
+  fn unlock_send_ping(&mut self) {
+    if !self.link.idle() {
+      return;
+    }
+    self.send_to_board(Message::UnlockPing { ping: self.ping });
+    self.ping_ticks = self.clock.get_tick_timer();
+    self.state = CarState::AwaitingPong;
+  }
+
+  /// Handle UNLOCK_PONG
+  fn unlock_send_chal(&mut self) {
+    // Every challenge gets a counter value of its own. Give up if they have
+    // all been used. Taking it only now means an UNLOCK_REQ that goes no
+    // further does not use one up
+    let Some(counter) = self.next_chal_counter() else {
+      // log!("Car: Out of challenges");
+      self.send_to_board(Message::UnlockRst);
+      self.leds.set_led(Led::Blue, false);
+      self.state = CarState::Idle;
+      return;
+    };
+
+    // Count the unlock as bad until the response checks out, so walking away
+    // from the challenge or cutting the power once the answer is known does
+    // not take it back
+    let fails = self.unlock_fails().saturating_add(1);
+    self.set_unlock_fails(fails);
+
+    // Initialize car nonce with random value :) it's very random. The first
+    // bytes are the counter, so the same nonce never comes up twice even if
+    // the randomness is bad
+    let mut car_nonce_b: [u8; LEN_NONCE] = [0; LEN_NONCE];
+    car_nonce_b[..LEN_CHAL_COUNTER].copy_from_slice(&counter.to_be_bytes());
+    self.rng.fill_bytes(&mut car_nonce_b[LEN_CHAL_COUNTER..]);
+    self.car_nonce = car_nonce_b;
+
+    // Get car secret key
+    let mut car_secret_w: [u32; LENW_CAR_SECRET] = [0; LENW_CAR_SECRET];
+    let mut car_secret_b: [u8; LEN_CAR_SECRET] = [0; LEN_CAR_SECRET];
+    self.storage.read(&mut car_secret_w, CARMEM_CAR_SECRET);
+    words_to_bytes(&car_secret_w, &mut car_secret_b);
+    let car_secret = SecretKey::from_bytes(car_secret_b).unwrap();
+
+    // Use the car secret key to sign the nonce, along with the car ID and
+    // everything sent so far
+    let car_id_b = self.car_id();
+    let transcript = chal_transcript(&car_id_b, &self.ping, &self.pong, &car_nonce_b);
+    let mut hedge: [u8; LEN_HEDGE] = [0; LEN_HEDGE];
+    self.rng.fill_bytes(&mut hedge);
+    let car_signed_nonce: [u8; LEN_SIG] = car_secret.sign_rfc6979(&transcript, &hedge).to_untagged_bytes();
+    self.car_nonce_sig = car_signed_nonce;
+
+    // Send unlock chal and nonce to fob
+    // log!("Car: Sending nonce: {:x?}", car_nonce_b);
+    // log!("Car: Sending nonce signature: {:x?}", car_signed_nonce);
+    self.send_to_board(Message::UnlockChal { nonce: car_nonce_b, nonce_sig: car_signed_nonce });
+    // log!("Car: Sent UNLOCK_CHAL to paired fob");
+
+    self.state = CarState::AwaitingResp;
+  }
+
+  /// Handle UNLOCK_RESP
+  fn unlock_check_resp(&mut self, fob_nonce_b: &[u8; LEN_NONCE], fob_signed_nonce: &[u8; LEN_SIG]) {
+    // log!("Car: Received nonce signature value: {:x?}", &fob_signed_nonce);
+
+    // The fob signs our own nonce and signature along with its nonce, so an
+    // answer to some other challenge or car does not check out
+    let car_id_b = self.car_id();
+    let transcript = resp_transcript(&car_id_b, &self.ping, &self.pong, &self.car_nonce, &self.car_nonce_sig, fob_nonce_b);
+
+    // Get fob public key
+    let mut fob_pubkey_w: [u32; LENW_FOB_PUBLIC] = [0; LENW_FOB_PUBLIC];
+    let mut fob_pubkey_b: [u8; LEN_FOB_PUBLIC] = [0; LEN_FOB_PUBLIC];
+    self.storage.read(&mut fob_pubkey_w, CARMEM_FOB_PUBLIC);
+    words_to_bytes(&fob_pubkey_w, &mut fob_pubkey_b);
+    let fob_pubkey = PublicKey::from_untagged_bytes(&fob_pubkey_b).unwrap();
+
+    // Load in the signature as a Signature type, and verify it with the
+    // message and public key
+    let mut fob_nonce_verified: bool = match Signature::from_untagged_bytes(fob_signed_nonce) {
+      Ok(fob_nonce_sig) => fob_pubkey.verify(&transcript, &fob_nonce_sig),
+      Err(_) => false,
+    };
+
+    // A calibration run sets the limit from this round trip, over the cable
+    // the car was calibrated with
+    let rtt_limit = self.rtt_limit();
+    if fob_nonce_verified && self.calibrating && rtt_limit == RTT_UNCALIBRATED {
+      let new_limit = self.rtt_ticks + self.rtt_ticks * RTT_SLACK_PERCENT / 100;
+      let new_limit = new_limit.min(RTT_UNCALIBRATED as u64 - 1) as u32;
+      self.set_rtt_limit(new_limit);
+      Message::HostCalibrated { rtt_limit: new_limit.to_be_bytes() }.send(&mut self.host);
+      // log!("Car: Calibrated, UNLOCK_PONG must arrive within {} ticks", new_limit);
+      self.calibrating = false;
+    } else if rtt_limit != RTT_UNCALIBRATED && self.rtt_ticks > rtt_limit as u64 {
+      // log!("Car: UNLOCK_PONG took {} ticks, the fob may be relayed", self.rtt_ticks);
+      fob_nonce_verified = false;
+    }
+
+    if fob_nonce_verified {
+      // Unlock once the 500ms are up
+      self.set_unlock_fails(0);
+      self.state = CarState::Unlocking;
+    } else {
+      // boo, bad signature or a slow fob. Refuse UNLOCK_REQ for a while, even
+      // across resets
+      // log!("Car: Bad signature, not unlocking");
+      self.leds.set_led(Led::Blue, false);
+      self.leds.set_led(Led::Red, true);
+      self.locked_until_us = self.clock.now_us() + lockout_us(self.unlock_fails());
+      self.deadline_us += US_UNLOCK_REJECT;
+      self.state = CarState::Rejecting;
+    }
+  }
+
+  /// Drops an unlock the fob stopped answering or reset. A challenge that
+  /// went out unanswered already counts as a bad response, so it locks the
+  /// car out the same way. Otherwise anyone could ask for challenges over
+  /// and over, using up counter values and EEPROM writes.
+  fn unlock_abandon(&mut self) {
+    if self.state == CarState::AwaitingResp {
+      // log!("Car: Challenge went unanswered");
+      self.locked_until_us = self.clock.now_us() + lockout_us(self.unlock_fails());
+    }
+    self.leds.set_led(Led::Blue, false);
+    self.leds.set_led(Led::Green, false);
+    self.state = CarState::Idle;
+  }
+
+  /// Read the car ID from EEPROM.
+  fn car_id(&mut self) -> [u8; LEN_CAR_ID] {
+    let mut car_id_w: [u32; LENW_CAR_ID] = [0; LENW_CAR_ID];
+    let mut car_id_b: [u8; LEN_CAR_ID] = [0; LEN_CAR_ID];
+    self.storage.read(&mut car_id_w, CARMEM_CAR_ID);
+    words_to_bytes(&car_id_w, &mut car_id_b);
+    car_id_b
+  }
+
+  /// Hands out the next challenge counter value, or `None` once there are
+  /// none left. Values are reserved in EEPROM a block at a time before any
+  /// of them is used, so a reset skips the rest of the block instead of
+  /// using a value twice.
+  fn next_chal_counter(&mut self) -> Option<u32> {
+    if self.chal_counter == self.chal_reserved {
+      if self.chal_reserved == u32::MAX {
+        return None;
+      }
+      self.chal_reserved = self.chal_reserved.saturating_add(CHAL_COUNTER_BLOCK);
+      let reserved_w: [u32; LENW_CHAL_COUNTER] = [self.chal_reserved; LENW_CHAL_COUNTER];
+      self.storage.write(&reserved_w, CARMEM_CHAL_COUNTER);
+    }
+    let counter = self.chal_counter;
+    self.chal_counter += 1;
+    Some(counter)
+  }
+
+  /// Read the first challenge counter value not yet reserved from EEPROM.
+  fn stored_chal_counter(&mut self) -> u32 {
+    let mut counter_w: [u32; LENW_CHAL_COUNTER] = [0; LENW_CHAL_COUNTER];
+    self.storage.read(&mut counter_w, CARMEM_CHAL_COUNTER);
+    counter_w[0]
+  }
+
+  /// Read the round trip limit in ticks from EEPROM. `RTT_UNCALIBRATED` if
+  /// the car was never calibrated.
+  fn rtt_limit(&mut self) -> u32 {
+    let mut limit_w: [u32; LENW_RTT_LIMIT] = [0; LENW_RTT_LIMIT];
+    self.storage.read(&mut limit_w, CARMEM_RTT_LIMIT);
+    limit_w[0]
+  }
+
+  /// Store the round trip limit in ticks in EEPROM.
+  fn set_rtt_limit(&mut self, limit: u32) {
+    let limit_w: [u32; LENW_RTT_LIMIT] = [limit; LENW_RTT_LIMIT];
+    self.storage.write(&limit_w, CARMEM_RTT_LIMIT);
+  }
+
+  /// Mix the seed saved on the last boot into the RNG and save a fresh one,
+  /// so a boot with little entropy still does not repeat an earlier one.
+  fn carry_rng_seed(&mut self) {
+    let mut seed_w: [u32; LENW_RNG_SEED] = [0; LENW_RNG_SEED];
+    let mut seed_b: [u8; LEN_RNG_SEED] = [0; LEN_RNG_SEED];
+    self.storage.read(&mut seed_w, CARMEM_RNG_SEED);
+    words_to_bytes(&seed_w, &mut seed_b);
+    let seed_b = next_rng_seed(&mut self.rng, &seed_b);
+    bytes_to_words(&seed_b, &mut seed_w);
+    self.storage.write(&seed_w, CARMEM_RNG_SEED);
+  }
+
+  /// Read the bad response count from EEPROM.
+  fn unlock_fails(&mut self) -> u32 {
+    let mut fails_w: [u32; LENW_UNLOCK_FAILS] = [0; LENW_UNLOCK_FAILS];
+    self.storage.read(&mut fails_w, CARMEM_UNLOCK_FAILS);
+    fails_w[0]
+  }
+
+  /// Store the bad response count in EEPROM.
+  fn set_unlock_fails(&mut self, fails: u32) {
+    let fails_w: [u32; LENW_UNLOCK_FAILS] = [fails; LENW_UNLOCK_FAILS];
+    self.storage.write(&fails_w, CARMEM_UNLOCK_FAILS);
+  }
+
+  /// Handle UNLOCK_FEAT
+  fn unlock_send_features(&mut self, feature_sigs: &[[u8; LEN_SIG]; NUM_FEATURES]) {
+    let [feature_sig1_b, feature_sig2_b, feature_sig3_b] = feature_sigs;
+
+    // Read in car ID from EEPROM
+    let car_id_b = self.car_id();
+
+    // Read in public key from EEPROM
+    let mut man_public_w: [u32; LENW_MAN_PUBLIC] = [0; LENW_MAN_PUBLIC];
+    let mut man_public_b: [u8; LEN_MAN_PUBLIC] = [0; LEN_MAN_PUBLIC];
+    self.storage.read(&mut man_public_w, CARMEM_MAN_PUBLIC);
+    words_to_bytes(&man_public_w, &mut man_public_b);
+
+    // Load in the public key as a PublicKey type
+    let man_public = PublicKey::from_untagged_bytes(&man_public_b).unwrap();
+
+    // Load in signatures as Signature types
+    let feature_sig1_res = Signature::from_untagged_bytes(feature_sig1_b);
+    let feature_sig2_res = Signature::from_untagged_bytes(feature_sig2_b);
+    let feature_sig3_res = Signature::from_untagged_bytes(feature_sig3_b);
+
+    // Concatenate car ID and feature numbers
+    let feature1_b: [u8; LEN_FEAT_NUM] = [0x00, 0x00, 0x00, 0x01];
+    let feature2_b: [u8; LEN_FEAT_NUM] = [0x00, 0x00, 0x00, 0x02];
+    let feature3_b: [u8; LEN_FEAT_NUM] = [0x00, 0x00, 0x00, 0x03];
+    let mut feat_pkg1: [u8; LEN_CAR_ID + LEN_FEAT_NUM] = [0; LEN_CAR_ID + LEN_FEAT_NUM];
+    let mut feat_pkg2: [u8; LEN_CAR_ID + LEN_FEAT_NUM] = [0; LEN_CAR_ID + LEN_FEAT_NUM];
+    let mut feat_pkg3: [u8; LEN_CAR_ID + LEN_FEAT_NUM] = [0; LEN_CAR_ID + LEN_FEAT_NUM];
+    feat_pkg1[..LEN_CAR_ID].copy_from_slice(&car_id_b);
+    feat_pkg1[LEN_CAR_ID..].copy_from_slice(&feature1_b);
+    feat_pkg2[..LEN_CAR_ID].copy_from_slice(&car_id_b);
+    feat_pkg2[LEN_CAR_ID..].copy_from_slice(&feature2_b);
+    feat_pkg3[..LEN_CAR_ID].copy_from_slice(&car_id_b);
+    feat_pkg3[LEN_CAR_ID..].copy_from_slice(&feature3_b);
+
+    // Go through each feature, and validate signature of (CAR_ID + FEAT_NUM) using manufacturer public key
+    // If correct, read the flag from EEPROM and send it to the host
+    let mut feature_msg_w: [u32; LENW_FLAG] = [0; LENW_FLAG];
+    let mut feature_msg_b: [u8; LEN_FLAG] = [0; LEN_FLAG];
+
+    // Verify feature 1
+    match feature_sig1_res {
+      Ok(feature_sig1) => {
+        if man_public.verify(&feat_pkg1, &feature_sig1) {
+          self.storage.read(&mut feature_msg_w, CARMEM_MSG_FEAT_1);
+          words_to_bytes(&feature_msg_w, &mut feature_msg_b);
+          self.host.write(&feature_msg_b);
+          // log!("Car: Feature 1 flag sent");
+        } else {
+          // log!("Car: Feature 1 signature invalid");
+        }
+      }
+      Err(_) => {
+        // log!("Car: Feature 1 signature invalid");
+      }
+    }
+
+    // Verify feature 2
+    match feature_sig2_res {
+      Ok(feature_sig2) => {
+        if man_public.verify(&feat_pkg2, &feature_sig2) {
+          self.storage.read(&mut feature_msg_w, CARMEM_MSG_FEAT_2);
+          words_to_bytes(&feature_msg_w, &mut feature_msg_b);
+          self.host.write(&feature_msg_b);
+          // log!("Car: Feature 2 flag sent");
+        } else {
+          // log!("Car: Feature 2 signature invalid");
+        }
+      }
+      Err(_) => {
+        // log!("Car: Feature 2 signature invalid");
+      }
+    }
+
+    // Verify feature 3
+    match feature_sig3_res {
+      Ok(feature_sig3) => {
+        if man_public.verify(&feat_pkg3, &feature_sig3) {
+          self.storage.read(&mut feature_msg_w, CARMEM_MSG_FEAT_3);
+          words_to_bytes(&feature_msg_w, &mut feature_msg_b);
+          self.host.write(&feature_msg_b);
+          // log!("Car: Feature 3 flag sent");
+        } else {
+          // log!("Car: Feature 3 signature invalid");
+        }
+      }
+      Err(_) => {
+        // log!("Car: Feature 3 signature invalid");
+      }
+    }
+
+    // log!("Car: All features processed");
+  }
+}
+
+/// How long UNLOCK_REQ is refused after `fails` bad responses in a row.
+fn lockout_us(fails: u32) -> u64 {
+  if fails == 0 {
+    return 0;
+  }
+  let doublings = (fails - 1).min(US_LOCKOUT_MAX.ilog2());
+  (US_LOCKOUT_BASE << doublings).min(US_LOCKOUT_MAX)
+}
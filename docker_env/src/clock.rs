@@ -0,0 +1,61 @@
+/// Frequency of the tick timer, which counts the 16 MHz PIOSC.
+pub const TICKS_PER_US: u64 = 16;
+
+/// The timers used to keep protocol timing. On the board these are the delay
+/// timer (TIMER0) and the tick timer (WTIMER0), see driverlib.rs. Off the
+/// board this can be a virtual clock that only moves when told to (see
+/// host.rs).
+pub trait Clock {
+    /// Waits for approximately the number of microseconds provided.
+    fn sleep_us(&mut self, us: u32);
+
+    /// Sets up the delay timer to trigger after the microseconds provided.
+    fn start_delay_timer_us(&mut self, us: u32);
+
+    /// Waits for delay timer to be completed.
+    fn wait_delay_timer(&mut self);
+
+    /// Gets remaining time on delay timer.
+    fn get_remaining_us_delay_timer(&mut self) -> u32;
+
+    /// Returns counter from PIOSC from startup.
+    fn get_tick_timer(&mut self) -> u64;
+
+    /// Returns the microseconds since startup. Protocol deadlines are kept
+    /// against this.
+    fn now_us(&mut self) -> u64 {
+        self.get_tick_timer() / TICKS_PER_US
+    }
+
+    /// Waits until at most the microseconds provided remain on the delay
+    /// timer.
+    fn wait_remaining_us_delay_timer(&mut self, us: u32) {
+        while self.get_remaining_us_delay_timer() > us {}
+    }
+}
+
+impl<T: Clock> Clock for &mut T {
+    fn sleep_us(&mut self, us: u32) {
+        (**self).sleep_us(us)
+    }
+
+    fn start_delay_timer_us(&mut self, us: u32) {
+        (**self).start_delay_timer_us(us)
+    }
+
+    fn wait_delay_timer(&mut self) {
+        (**self).wait_delay_timer()
+    }
+
+    fn get_remaining_us_delay_timer(&mut self) -> u32 {
+        (**self).get_remaining_us_delay_timer()
+    }
+
+    fn get_tick_timer(&mut self) -> u64 {
+        (**self).get_tick_timer()
+    }
+
+    fn wait_remaining_us_delay_timer(&mut self, us: u32) {
+        (**self).wait_remaining_us_delay_timer(us)
+    }
+}
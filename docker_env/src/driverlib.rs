@@ -1,3 +1,7 @@
+use crate::clock::Clock;
+use crate::storage::{Storage, EEPROM_SIZE};
+use crate::transport::Transport;
+
 mod driverwrapper {
     #[link(name = "driverwrapper")]
     extern "C" {
@@ -8,6 +12,8 @@ mod driverwrapper {
         pub(super) fn uart_readb_board() -> i32;
         pub(super) fn uart_writeb_host(data: u8);
         pub(super) fn uart_writeb_board(data: u8);
+        pub(super) fn uart_overflows_host() -> u32;
+        pub(super) fn uart_overflows_board() -> u32;
         pub(super) fn eeprom_read(data: *mut u32, address: u32, length: u32);
         pub(super) fn eeprom_write(data: *const u32, address: u32, length: u32);
         pub(super) fn read_sw_1() -> bool;
@@ -20,8 +26,6 @@ mod driverwrapper {
     }
 }
 
-const EEPROM_SIZE: u32 = 0x800; // 2K
-
 /// Set up the system. This should be called after Board::new().
 pub fn init_system() {
     unsafe {
@@ -29,7 +33,8 @@ pub fn init_system() {
     }
 }
 
-/// Check if the host has sent a byte.
+/// Check if the host has sent a byte. Bytes are received in the background by
+/// the UART interrupt handlers, so none are lost while the CPU is busy.
 pub fn uart_avail_host() -> bool {
     unsafe { driverwrapper::uart_avail_host() }
 }
@@ -39,7 +44,7 @@ pub fn uart_avail_board() -> bool {
     unsafe { driverwrapper::uart_avail_board() }
 }
 
-/// Read a byte from the host.
+/// Read a byte from the host. Blocks until one arrives.
 pub fn uart_readb_host() -> u8 {
     // return as u8
     let ret: i32 = unsafe { driverwrapper::uart_readb_host() };
@@ -53,7 +58,7 @@ pub fn uart_read_host(data: &mut [u8]) {
     }
 }
 
-/// Read a byte from the board.
+/// Read a byte from the board. Blocks until one arrives.
 pub fn uart_readb_board() -> u8 {
     let ret: i32 = unsafe { driverwrapper::uart_readb_board() };
     ret as u8
@@ -92,6 +97,53 @@ pub fn uart_write_board(data: &[u8]) {
         uart_writeb_board(*byte);
     }
 }
+
+/// Number of times bytes from the host were lost because the receive buffer
+/// or the UART FIFO was full.
+pub fn uart_overflows_host() -> u32 {
+    unsafe { driverwrapper::uart_overflows_host() }
+}
+
+/// Number of times bytes from the board were lost because the receive buffer
+/// or the UART FIFO was full.
+pub fn uart_overflows_board() -> u32 {
+    unsafe { driverwrapper::uart_overflows_board() }
+}
+
+/// UART0, used to communicate with the host computer.
+pub struct HostUart;
+
+impl Transport for HostUart {
+    fn avail(&mut self) -> bool {
+        uart_avail_host()
+    }
+
+    fn readb(&mut self) -> u8 {
+        uart_readb_host()
+    }
+
+    fn writeb(&mut self, data: u8) {
+        uart_writeb_host(data)
+    }
+}
+
+/// UART1, used to communicate with the other board.
+pub struct BoardUart;
+
+impl Transport for BoardUart {
+    fn avail(&mut self) -> bool {
+        uart_avail_board()
+    }
+
+    fn readb(&mut self) -> u8 {
+        uart_readb_board()
+    }
+
+    fn writeb(&mut self, data: u8) {
+        uart_writeb_board(data)
+    }
+}
+
 /// Read from the EEPROM. Address must be a multiple of 4.
 pub fn eeprom_read(data: &mut [u32], address: u32) {
     if data.len() == 0 {
@@ -114,6 +166,19 @@ pub fn eeprom_write(data: &[u32], address: u32) {
     }
 }
 
+/// The on-chip EEPROM.
+pub struct Eeprom;
+
+impl Storage for Eeprom {
+    fn read(&mut self, data: &mut [u32], address: u32) {
+        eeprom_read(data, address)
+    }
+
+    fn write(&mut self, data: &[u32], address: u32) {
+        eeprom_write(data, address)
+    }
+}
+
 /// Check if switch 1 is pressed. Returns true if pressed.
 pub fn read_sw_1() -> bool {
     unsafe { driverwrapper::read_sw_1() }
@@ -149,4 +214,29 @@ pub fn get_remaining_us_delay_timer() -> u32 {
 /// Returns counter from PIOSC from startup
 pub fn get_tick_timer() -> u64 {
     unsafe { driverwrapper::get_tick_timer() }
+}
+
+/// The delay timer (TIMER0) and the tick timer (WTIMER0).
+pub struct Timers;
+
+impl Clock for Timers {
+    fn sleep_us(&mut self, us: u32) {
+        sleep_us(us)
+    }
+
+    fn start_delay_timer_us(&mut self, us: u32) {
+        start_delay_timer_us(us)
+    }
+
+    fn wait_delay_timer(&mut self) {
+        wait_delay_timer()
+    }
+
+    fn get_remaining_us_delay_timer(&mut self) -> u32 {
+        get_remaining_us_delay_timer()
+    }
+
+    fn get_tick_timer(&mut self) -> u64 {
+        get_tick_timer()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,895 @@
+use p256_cortex_m4::{SecretKey, Signature, PublicKey};
+
+use crate::{
+  log, words_to_bytes, bytes_to_words, ct_eq, Signer, Verifier, Clock, Event, Led, Leds, Storage, Port, Transport,
+  aead::{LEN_AEAD_NONCE, LEN_KEY},
+  link::Link,
+  pin::{calibrate_iterations, PinHash, MAX_PIN_HASH_ITERATIONS, MIN_PIN_HASH_ITERATIONS},
+  protocol::{
+    chal_transcript, open_pin, pair_key, resp_transcript, seal_pin, Message, MsgReader, PairSecrets,
+    LEN_CAR_ID, LEN_CHAL_COUNTER, LEN_FEAT_NUM, LEN_NONCE, LEN_PIN, LEN_PING, LEN_PUBLIC, LEN_SIG
+  },
+  rfc6979::LEN_HEDGE,
+  rng::{next_rng_seed, EventRng, LEN_RNG_SEED},
+  wrap::{wrap, unwrap, LEN_WRAPPED_KEY}
+};
+
+/**
+ * EEPROM state addresses (specifically for fob)
+ */
+pub const FOBMEM_FOB_SECRET:      u32 = 0x100;
+pub const FOBMEM_FOB_SALT:        u32 = 0x140;
+pub const FOBMEM_PIN_FAILS:       u32 = 0x14C;
+pub const FOBMEM_NEXT_CHAL:       u32 = 0x150;
+pub const FOBMEM_PIN_HASH_ITERS:  u32 = 0x15C;
+pub const FOBMEM_PIN_HASH:        u32 = 0x160;
+pub const FOBMEM_FOB_SECRET_ENC:  u32 = 0x180;
+pub const FOBMEM_RNG_SEED:        u32 = 0x1E0;
+pub const FOBMEM_CAR_ID:          u32 = 0x200;
+pub const FOBMEM_FEAT_1_SIG:      u32 = 0x240;
+pub const FOBMEM_FEAT_2_SIG:      u32 = 0x280;
+pub const FOBMEM_FEAT_3_SIG:      u32 = 0x2C0;
+pub const FOBMEM_CAR_PUBLIC:      u32 = 0x300;
+pub const FOBMEM_FOB_IS_PAIRED:   u32 = 0x400;
+
+/**
+ * EEPROM state lengths
+ */
+// in bytes (for sending over UART)
+const LEN_FOB_SECRET:         usize = 32;
+const LEN_CAR_PUBLIC:         usize = 64;
+
+// in words (for accesing EEPROM)
+const LENW_FOB_SECRET:        usize = LEN_FOB_SECRET / 4;
+const LENW_CAR_PUBLIC:        usize = LEN_CAR_PUBLIC / 4;
+const LENW_CAR_ID:            usize = LEN_CAR_ID / 4;
+const LENW_FEAT_SIG:          usize = LEN_SIG / 4;
+
+// Pairing specific state
+const LEN_FOB_SECRET_ENC:     usize = LEN_WRAPPED_KEY;
+const LEN_FOB_SALT:           usize = 12;
+const LEN_PIN_FAILS:          usize = 4;
+const LEN_NEXT_CHAL:          usize = LEN_CHAL_COUNTER;
+const LEN_PIN_HASH_ITERS:     usize = 4;
+const LEN_PIN_HASH:           usize = 32;
+const LEN_FOB_IS_PAIRED:      usize = 4;
+
+const LENW_FOB_SECRET_ENC:    usize = LEN_FOB_SECRET_ENC / 4;
+const LENW_FOB_SALT:          usize = LEN_FOB_SALT / 4;
+const LENW_PIN_FAILS:         usize = LEN_PIN_FAILS / 4;
+const LENW_NEXT_CHAL:         usize = LEN_NEXT_CHAL / 4;
+const LENW_PIN_HASH_ITERS:    usize = LEN_PIN_HASH_ITERS / 4;
+const LENW_PIN_HASH:          usize = LEN_PIN_HASH / 4;
+const LENW_FOB_IS_PAIRED:     usize = LEN_FOB_IS_PAIRED / 4;
+const LENW_RNG_SEED:          usize = LEN_RNG_SEED / 4;
+
+/**
+ * Temporary state lengths
+ */
+const LENW_FEAT_NUM:          usize = LEN_FEAT_NUM / 4;
+
+/**
+ * Timing
+ */
+const US_PAIR:                u64 = 1_000_000; // every pairing takes this long
+const US_PAIR_ACK:            u64 = 500_000; // PAIR_ACK must have arrived by now, leaving PAIR_FIN time to get across
+const US_PAIR_FAIL:           u64 = 5_000_000; // a wrong PIN takes this long
+const US_ENABLE:              u64 = 800_000;
+const US_PAIR_FIN_TIMEOUT:    u64 = 1_000_000; // PAIR_FIN must arrive this soon after PAIR_SYN
+const US_CHAL_TIMEOUT:        u64 = 1_000_000; // UNLOCK_PING and UNLOCK_CHAL must arrive this soon after UNLOCK_REQ
+const US_GOOD_TIMEOUT:        u64 = 1_000_000; // UNLOCK_GOOD must arrive this soon after UNLOCK_RESP
+const US_FLASH:               u64 = 1_000_000; // status LED stays on this long
+
+/**
+ * PIN lockout
+ */
+const FREE_PIN_FAILS:         u32 = 3; // wrong PINs before the lockout starts
+const US_LOCKOUT_BASE:        u64 = 10_000_000; // doubles with every wrong PIN after that
+const US_LOCKOUT_MAX:         u64 = 3_600_000_000;
+
+/// Where the fob is in the pairing, enabling and unlock protocols.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FobState {
+  /// Waiting for a host command, PAIR_SYN or a button press
+  Idle,
+  /// Sent UNLOCK_REQ, waiting for UNLOCK_PING
+  AwaitingPing,
+  /// Sent UNLOCK_PONG, waiting for UNLOCK_CHAL
+  AwaitingChallenge,
+  /// Sent UNLOCK_RESP, waiting for UNLOCK_GOOD
+  AwaitingGood,
+  /// Got the feature package, waiting before storing it
+  EnablingFeature,
+  /// Paired fob: sent PAIR_SYN, waiting for PAIR_ACK
+  PairingPrimary,
+  /// Paired fob: the PIN was wrong, waiting to send PAIR_RST
+  RejectingPin,
+  /// Unpaired fob: sent PAIR_ACK, waiting for PAIR_FIN
+  PairingSecondary,
+}
+
+/// The fob firmware as a state machine. Everything it talks to is passed in,
+/// so the same code runs on the board (see bin/fob.rs) and on a computer (see
+/// bin/sim.rs). Feed it events with `on_byte()`, `on_button()` and
+/// `on_tick()`, or call `poll()` to read them from the UARTs and clock.
+pub struct Fob<H, B, S, C, L, R> {
+  /// UART to the host computer
+  pub host: H,
+  /// UART to the car or the other fob
+  pub board_link: B,
+  /// Fob EEPROM
+  pub storage: S,
+  /// Delay and tick timers
+  pub clock: C,
+  /// Status LEDs
+  pub leds: L,
+  rng: R,
+  state: FobState,
+  host_rx: MsgReader,
+  link: Link,
+  pin: [u8; LEN_PIN],
+  pin_hash: Option<PinHash>,
+  pair_reply: Option<Message>,
+  eph_secret: Option<SecretKey>,
+  eph_public: [u8; LEN_PUBLIC],
+  pair_key: Option<[u8; LEN_KEY]>,
+  ping: [u8; LEN_PING],
+  pong: [u8; LEN_PING],
+  feature_num: [u8; LEN_FEAT_NUM],
+  feature_sig: [u8; LEN_SIG],
+  flash_led: Option<Led>,
+  flash_until_us: u64,
+  deadline_us: u64,
+  locked_until_us: u64,
+}
+
+impl<H, B, S, C, L, R> Fob<H, B, S, C, L, R>
+where
+  H: Transport,
+  B: Transport,
+  S: Storage,
+  C: Clock,
+  L: Leds,
+  R: EventRng,
+{
+  /// Sets up the fob. `rng` should already be seeded with entropy. A fob
+  /// that was locked out after too many wrong PINs serves the whole lockout
+  /// again from here, since there is no telling how long it was off.
+  pub fn new(host: H, board_link: B, storage: S, clock: C, leds: L, rng: R) -> Self {
+    let mut fob = Fob {
+      host, board_link, storage, clock, leds, rng,
+      state: FobState::Idle,
+      host_rx: MsgReader::new(),
+      link: Link::new(),
+      pin: [0; LEN_PIN],
+      pin_hash: None,
+      pair_reply: None,
+      eph_secret: None,
+      eph_public: [0; LEN_PUBLIC],
+      pair_key: None,
+      ping: [0; LEN_PING],
+      pong: [0; LEN_PING],
+      feature_num: [0; LEN_FEAT_NUM],
+      feature_sig: [0; LEN_SIG],
+      flash_led: None,
+      flash_until_us: 0,
+      deadline_us: 0,
+      locked_until_us: 0,
+    };
+    fob.carry_rng_seed();
+    fob.calibrate_pin_hash();
+    fob.locked_until_us = fob.clock.now_us() + lockout_us(fob.pin_fails());
+    fob
+  }
+
+  /// Current protocol state.
+  pub fn state(&self) -> FobState {
+    self.state
+  }
+
+  /// Runs one iteration of the main loop. Hands a button press to
+  /// `on_button()` and every byte received to `on_byte()`, then checks the
+  /// clock with `on_tick()`.
+  pub fn poll(&mut self, sw1_pressed: bool) {
+    if sw1_pressed {
+      self.on_button();
+    }
+    while self.host.avail() {
+      let byte: u8 = self.host.readb();
+      self.on_byte(Port::Host, byte);
+    }
+    while self.board_link.avail() {
+      let byte: u8 = self.board_link.readb();
+      self.on_byte(Port::BoardLink, byte);
+    }
+    self.on_tick();
+  }
+
+  /// Handles a press of SW1. A paired fob tries to unlock the car.
+  pub fn on_button(&mut self) {
+    self.add_event(Event::Button);
+    if self.state == FobState::Idle && self.is_paired() {
+      self.request_unlock();
+    }
+  }
+
+  /// Handles a byte received on one of the UARTs.
+  pub fn on_byte(&mut self, port: Port, byte: u8) {
+    let now_us = self.clock.now_us();
+    self.add_event(Event::UartByte(port));
+    // Messages from the host are bare, the board link has its own framing
+    let received = match port {
+      Port::Host => self.host_rx.push_at(byte, now_us).and_then(Result::ok),
+      Port::BoardLink => self.link.on_byte(byte, now_us, &mut self.board_link),
+    };
+    if let Some(msg) = received {
+      self.on_message(port, msg);
+    }
+  }
+
+  /// Handles a whole message from the host or the board link.
+  fn on_message(&mut self, port: Port, msg: Message) {
+    match (self.state, port, msg) {
+      (FobState::Idle, Port::Host, Message::PairReq { pin }) => {
+        let now_us = self.clock.now_us();
+        if self.is_paired() && now_us < self.locked_until_us {
+          // log!("Paired fob: Locked out after too many wrong PINs");
+          let ms_left = (self.locked_until_us - now_us).div_ceil(1_000).min(u32::MAX as u64) as u32;
+          Message::HostLocked { ms_left: ms_left.to_be_bytes() }.send(&mut self.host);
+          self.flash(Led::Red, now_us + US_FLASH);
+        } else if self.is_paired() {
+          // log!("Paired fob: Received PAIR_REQ");
+          self.leds.set_led(Led::Blue, true);
+          self.deadline_us = self.clock.now_us() + US_PAIR;
+          self.paired_fob_pairing_start(pin);
+        } else {
+          // log!("Unpaired fob: Received invalid PAIR_REQ");
+          self.host_failure();
+        }
+      }
+      (FobState::Idle, Port::Host, Message::EnabFeat { feature_num, feature_sig, .. }) => {
+        if self.is_paired() {
+          // log!("Paired fob: Received ENAB_FEAT");
+          self.leds.set_led(Led::Green, true);
+          self.feature_num = feature_num;
+          self.feature_sig = feature_sig;
+          self.deadline_us = self.clock.now_us() + US_ENABLE;
+          self.state = FobState::EnablingFeature;
+        } else {
+          // log!("Unpaired fob: Received invalid ENAB_FEAT");
+          self.host_failure();
+        }
+      }
+      (FobState::Idle, Port::BoardLink, Message::PairSyn { eph_public: syn_public }) if !self.is_paired() => {
+        // log!("Unpaired fob: Received PAIR_SYN");
+        // Our half of the key exchange, and the PAIR_PIN and PAIR_FIN key
+        let eph_secret = SecretKey::random(&mut self.rng);
+        let ack_public = eph_secret.public_key().to_untagged_bytes();
+        let Some(key) = pair_key(&eph_secret, &syn_public, &syn_public, &ack_public) else {
+          // log!("Unpaired fob: Invalid PAIR_SYN public key");
+          return;
+        };
+        self.leds.set_led(Led::Blue, true);
+        self.pair_key = Some(key);
+        // Send PAIR_ACK to paired fob
+        self.send_to_board(Message::PairAck { eph_public: ack_public });
+        // log!("Unpaired fob: Sent PAIR_ACK to paired fob");
+        self.deadline_us = self.clock.now_us() + US_PAIR_FIN_TIMEOUT;
+        self.state = FobState::PairingSecondary;
+      }
+      (FobState::PairingPrimary, Port::BoardLink, Message::PairAck { eph_public: ack_public }) if self.pair_reply.is_none() => {
+        // log!("Paired fob: Received PAIR_ACK");
+        self.paired_fob_pairing_ack(&ack_public);
+        self.pair_reply = Some(Message::PairAck { eph_public: ack_public });
+      }
+      // Only the first reply counts
+      (FobState::PairingPrimary, Port::BoardLink, reply) if self.pair_reply.is_none() => {
+        self.pair_reply = Some(reply);
+      }
+      (FobState::PairingSecondary, Port::BoardLink, Message::PairPin { sealed, tag }) if self.pin_hash.is_none() => {
+        // log!("Unpaired fob: Received PAIR_PIN");
+        // Get the new PIN hash done while waiting for PAIR_FIN
+        let Some(pin) = self.pair_key.and_then(|key| open_pin(&sealed, &tag, &key)) else {
+          // log!("Unpaired fob: PAIR_PIN failed authentication");
+          return;
+        };
+        let salt = self.fob_salt();
+        let iterations = self.pin_hash_iterations();
+        self.pin_hash = Some(PinHash::new(&pin, &salt, iterations));
+      }
+      (FobState::PairingSecondary, Port::BoardLink, Message::PairFin { sealed, tag }) => {
+        // log!("Unpaired fob: Received PAIR_FIN");
+        // Nothing is stored unless PAIR_FIN decrypts with the key we agreed on
+        if let Some(secrets) = self.pair_key.and_then(|key| PairSecrets::open(&sealed, &tag, &key)) {
+          self.unpaired_fob_pairing(&secrets);
+        } else {
+          // log!("Unpaired fob: PAIR_FIN failed authentication");
+        }
+        self.unpaired_fob_pairing_done();
+      }
+      (FobState::PairingSecondary, Port::BoardLink, Message::PairRst) => {
+        // log!("Unpaired fob: Received PAIR_RST");
+        // log!("Unpaired fob: PAIR transaction failed");
+        self.unpaired_fob_pairing_done();
+      }
+      (FobState::AwaitingPing, Port::BoardLink, Message::UnlockPing { ping }) => {
+        // The car is timing this, so answer before doing anything else. The
+        // pong was picked in advance
+        self.ping = ping;
+        self.send_to_board(Message::UnlockPong { pong: self.pong });
+        self.state = FobState::AwaitingChallenge;
+      }
+      (FobState::AwaitingChallenge, Port::BoardLink, Message::UnlockChal { nonce, nonce_sig }) => {
+        log!("Fob: Received UNLOCK_CHAL from car");
+        self.unlock_respond(&nonce, &nonce_sig);
+      }
+      (FobState::AwaitingGood, Port::BoardLink, Message::UnlockGood) if self.is_paired() => {
+        log!("Fob: Received UNLOCK_GOOD");
+        self.leds.set_led(Led::Green, true);
+        self.unlock_send_features();
+        self.leds.set_led(Led::Green, false);
+        self.state = FobState::Idle;
+      }
+      (FobState::AwaitingPing | FobState::AwaitingChallenge | FobState::AwaitingGood, Port::BoardLink, Message::UnlockRst) => {
+        log!("Fob: Received UNLOCK_RST");
+        self.state = FobState::Idle;
+      }
+      (_, _, _msg) => {
+        // log!("Fob: Received unexpected message: {:?}", _msg);
+      }
+    }
+  }
+
+  /// Handles the passing of time. Finishes whatever is waiting on the clock.
+  pub fn on_tick(&mut self) {
+    let now_us = self.clock.now_us();
+    if let Some(led) = self.flash_led {
+      if now_us >= self.flash_until_us {
+        self.leds.set_led(led, false);
+        self.flash_led = None;
+      }
+    }
+    // Drop a host message that stopped halfway, and resend anything the
+    // other board has not acknowledged yet
+    self.host_rx.expire(now_us);
+    self.link.on_tick(now_us, &mut self.board_link);
+    // Work on the PIN hash while waiting on the other fob
+    let hashed = match &mut self.pin_hash {
+      Some(pin_hash) => pin_hash.step(),
+      None => false,
+    };
+    match self.state {
+      FobState::AwaitingPing | FobState::AwaitingChallenge | FobState::AwaitingGood if now_us >= self.deadline_us => {
+        log!("Fob: Car stopped answering");
+        self.link.reset();
+        self.send_to_board(Message::UnlockRst);
+        self.leds.set_led(Led::Blue, false);
+        self.state = FobState::Idle;
+      }
+      FobState::PairingSecondary if now_us >= self.deadline_us => {
+        // log!("Unpaired fob: PAIR_FIN timeout");
+        self.link.reset();
+        self.send_to_board(Message::PairRst);
+        self.unpaired_fob_pairing_done();
+      }
+      // Finish as soon as both the PIN hash and PAIR_ACK are in, so PAIR_FIN
+      // has most of the second to get across
+      FobState::PairingPrimary if hashed && self.pair_reply.is_some() => {
+        self.paired_fob_pairing_finish();
+      }
+      FobState::PairingPrimary if now_us + (US_PAIR - US_PAIR_ACK) >= self.deadline_us => {
+        self.paired_fob_pairing_finish();
+      }
+      FobState::RejectingPin if now_us >= self.deadline_us => {
+        self.send_to_board(Message::PairRst);
+        // log!("Paired fob: Sent PAIR_RST to unpaired fob");
+        // log!("Paired fob: PAIR transaction failed");
+        self.leds.set_led(Led::Blue, false);
+        self.state = FobState::Idle;
+      }
+      FobState::EnablingFeature if now_us >= self.deadline_us => {
+        self.enable_feature();
+        self.leds.set_led(Led::Green, false);
+        self.state = FobState::Idle;
+      }
+      _ => {}
+    }
+  }
+
+  /// Adds something the fob saw to the RNG's entropy pool, along with when
+  /// it happened.
+  fn add_event(&mut self, event: Event) {
+    let ticks = self.clock.get_tick_timer();
+    self.rng.add_event(event, ticks);
+  }
+
+  /// Send a message to the other board. If the queue is full the other board
+  /// has stopped answering, and the protocol timeouts take care of it.
+  fn send_to_board(&mut self, msg: Message) {
+    let _ = self.link.send(&msg, &mut self.board_link);
+  }
+
+  /// Tell the host a command failed and flash the red LED.
+  fn host_failure(&mut self) {
+    Message::HostFailure.send(&mut self.host);
+    let until_us = self.clock.now_us() + US_FLASH;
+    self.flash(Led::Red, until_us);
+    self.state = FobState::Idle;
+  }
+
+  /// Turn an LED on until the given time. The fob keeps handling events in
+  /// the meantime.
+  fn flash(&mut self, led: Led, until_us: u64) {
+    if let Some(previous) = self.flash_led {
+      self.leds.set_led(previous, false);
+    }
+    self.leds.set_led(led, true);
+    self.flash_led = Some(led);
+    self.flash_until_us = until_us;
+  }
+
+  /// Handle PAIR_REQ
+  fn paired_fob_pairing_start(&mut self, pin: [u8; LEN_PIN]) {
+    // log!("Paired fob: PAIR_REQ PIN value: {:x?}", pin);
+
+    // 1. Send PAIR_SYN and our half of the key exchange to unpaired fob. The
+    // PIN attempt only follows in PAIR_PIN, once it can be encrypted
+    let eph_secret = SecretKey::random(&mut self.rng);
+    self.eph_public = eph_secret.public_key().to_untagged_bytes();
+    self.eph_secret = Some(eph_secret);
+    self.pin = pin;
+    self.send_to_board(Message::PairSyn { eph_public: self.eph_public });
+    log!("Paired fob: Sent PAIR_SYN to unpaired fob");
+
+    // 2. Start hashing FOB_SALT + PIN, a little on every tick while we wait
+    let salt = self.fob_salt();
+    let iterations = self.pin_hash_iterations();
+    self.pin_hash = Some(PinHash::new(&pin, &salt, iterations));
+
+    // Wait until 500ms for PAIR_ACK
+    self.pair_reply = None;
+    self.state = FobState::PairingPrimary;
+  }
+
+  /// Handle PAIR_ACK: agree on the PAIR_PIN and PAIR_FIN key with the
+  /// unpaired fob, and pass the PIN attempt on so it can start hashing it
+  fn paired_fob_pairing_ack(&mut self, ack_public: &[u8; LEN_PUBLIC]) {
+    let pin = core::mem::take(&mut self.pin);
+    let Some(eph_secret) = self.eph_secret.take() else { return };
+    self.pair_key = pair_key(&eph_secret, ack_public, &self.eph_public, ack_public);
+    if let Some(key) = self.pair_key {
+      self.send_to_board(seal_pin(&pin, &key));
+      // log!("Paired fob: Sent PAIR_PIN to unpaired fob");
+    }
+  }
+
+  /// Finish PAIR_REQ once the unpaired fob has had time to answer
+  fn paired_fob_pairing_finish(&mut self) {
+    let pin_hash = self.pin_hash.take();
+    self.pin = [0; LEN_PIN];
+    self.eph_secret = None;
+
+    // 3. Check PAIR_ACK came, with a good key
+    let key = match self.pair_reply.take() {
+      Some(Message::PairAck { .. }) => self.pair_key.take(),
+      Some(_msg) => {
+        // log!("Paired fob: Received unexpected message: {:?}", _msg);
+        None
+      }
+      None => {
+        // log!("Paired fob: PAIR_ACK timeout, could not find unpaired fob");
+        None
+      }
+    };
+    let (Some(key), Some(pin_hash)) = (key, pin_hash) else {
+      self.leds.set_led(Led::Blue, false);
+      self.state = FobState::Idle;
+      return
+    };
+
+    // 4. Finish the hash of FOB_SALT + PIN, if the ticks so far have not
+    let saltpin_hash = pin_hash.finish();
+
+    // 5. Count the attempt as a failure before checking it, so cutting the
+    // power once the answer is known does not take it back
+    let fails = self.pin_fails().saturating_add(1);
+    self.set_pin_fails(fails);
+
+    // 6. Compare with the stored hash, in constant time
+    let mut eeprom_pin_hash_w: [u32; LENW_PIN_HASH] = [0; LENW_PIN_HASH];
+    let mut eeprom_pin_hash: [u8; LEN_PIN_HASH] = [0; LEN_PIN_HASH];
+    self.storage.read(&mut eeprom_pin_hash_w, FOBMEM_PIN_HASH);
+    words_to_bytes(&eeprom_pin_hash_w, &mut eeprom_pin_hash);
+    if ct_eq(&eeprom_pin_hash, &saltpin_hash.check) {
+      // PIN is correct, transmit PAIR_FIN
+      // log!("Paired fob: PIN is correct");
+
+      let mut secret_enc_w: [u32; LENW_FOB_SECRET_ENC] = [0; LENW_FOB_SECRET_ENC];
+      let mut car_id_w: [u32; LENW_CAR_ID] = [0; LENW_CAR_ID];
+      let mut feature_sig1_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+      let mut feature_sig2_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+      let mut feature_sig3_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+      let mut car_public_w: [u32; LENW_CAR_PUBLIC] = [0; LENW_CAR_PUBLIC];
+
+      let mut secret_enc: [u8; LEN_FOB_SECRET_ENC] = [0; LEN_FOB_SECRET_ENC];
+      let mut car_id: [u8; LEN_CAR_ID] = [0; LEN_CAR_ID];
+      let mut feature_sig1: [u8; LEN_SIG] = [0; LEN_SIG];
+      let mut feature_sig2: [u8; LEN_SIG] = [0; LEN_SIG];
+      let mut feature_sig3: [u8; LEN_SIG] = [0; LEN_SIG];
+      let mut car_public: [u8; LEN_CAR_PUBLIC] = [0; LEN_CAR_PUBLIC];
+
+      self.storage.read(&mut secret_enc_w, FOBMEM_FOB_SECRET_ENC);
+      self.storage.read(&mut car_id_w, FOBMEM_CAR_ID);
+      self.storage.read(&mut feature_sig1_w, FOBMEM_FEAT_1_SIG);
+      self.storage.read(&mut feature_sig2_w, FOBMEM_FEAT_2_SIG);
+      self.storage.read(&mut feature_sig3_w, FOBMEM_FEAT_3_SIG);
+      self.storage.read(&mut car_public_w, FOBMEM_CAR_PUBLIC);
+
+      words_to_bytes(& secret_enc_w, &mut secret_enc);
+      words_to_bytes(& car_id_w, &mut car_id);
+      words_to_bytes(& feature_sig1_w, &mut feature_sig1);
+      words_to_bytes(& feature_sig2_w, &mut feature_sig2);
+      words_to_bytes(& feature_sig3_w, &mut feature_sig3);
+      words_to_bytes(& car_public_w, &mut car_public);
+
+      // Unwrap FOB_SECRET_ENC with the key from the PIN hash. The PIN hash
+      // matched, so this only fails if the EEPROM was changed.
+      let secret = match unwrap(&secret_enc, &saltpin_hash.wrap_key) {
+        Ok(secret) => secret,
+        Err(_) => {
+          // log!("Paired fob: FOB_SECRET_ENC did not unwrap");
+          self.locked_until_us = self.clock.now_us() + lockout_us(fails);
+          self.deadline_us += US_PAIR_FAIL - US_PAIR;
+          self.state = FobState::RejectingPin;
+          return
+        }
+      };
+      self.set_pin_fails(0);
+
+      // log!("secret {:x?}", secret);
+      // log!("car_id {:x?}", car_id);
+      // log!("feature_sig1 {:x?}", feature_sig1);
+      // log!("feature_sig2 {:x?}", feature_sig2);
+      // log!("feature_sig3 {:x?}", feature_sig3);
+      // log!("car_public {:x?}", car_public);
+
+      // Only the unpaired fob can decrypt this
+      let pair_fin = PairSecrets {
+        fob_secret: secret,
+        car_id,
+        feature_sigs: [feature_sig1, feature_sig2, feature_sig3],
+        car_public,
+        next_chal: self.next_chal().to_be_bytes(),
+      }.seal(&key);
+      self.send_to_board(pair_fin);
+      // log!("Paired fob: Sent PAIR_FIN to unpaired fob");
+
+      // Blue until the second is up
+      self.flash(Led::Blue, self.deadline_us);
+      self.state = FobState::Idle;
+    } else {
+      // PIN is incorrect, block for 5 seconds and then send PAIR_RST. After
+      // a few of these, PAIR_REQ is refused until the lockout is over.
+      // log!("Paired fob: PIN is incorrect");
+      self.locked_until_us = self.clock.now_us() + lockout_us(fails);
+      self.deadline_us += US_PAIR_FAIL - US_PAIR;
+      self.state = FobState::RejectingPin;
+    }
+  }
+
+  /// Handle PAIR_FIN
+  fn unpaired_fob_pairing(&mut self, secrets: &PairSecrets) {
+    // 4. Receive data from paired fob
+    let PairSecrets { fob_secret: secret, car_id, feature_sigs, car_public, next_chal } = secrets;
+    let [feature_sig1, feature_sig2, feature_sig3] = feature_sigs;
+    // log!("secret {:x?}", secret);
+    // log!("car_id {:x?}", car_id);
+    // log!("feature_sig1 {:x?}", feature_sig1);
+    // log!("feature_sig2 {:x?}", feature_sig2);
+    // log!("feature_sig3 {:x?}", feature_sig3);
+    // log!("car_public {:x?}", car_public);
+
+    // 5. Convert from bytes to words
+    let mut secret_w: [u32; LENW_FOB_SECRET] = [0; LENW_FOB_SECRET];
+    let mut car_id_w: [u32; LENW_CAR_ID] = [0; LENW_CAR_ID];
+    let mut feature_sig1_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+    let mut feature_sig2_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+    let mut feature_sig3_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+    let mut car_public_w: [u32; LENW_CAR_PUBLIC] = [0; LENW_CAR_PUBLIC];
+
+    bytes_to_words(secret, &mut secret_w);
+    bytes_to_words(car_id, &mut car_id_w);
+    bytes_to_words(feature_sig1, &mut feature_sig1_w);
+    bytes_to_words(feature_sig2, &mut feature_sig2_w);
+    bytes_to_words(feature_sig3, &mut feature_sig3_w);
+    bytes_to_words(car_public, &mut car_public_w);
+
+    // 6. Finish the new PIN hash of FOB_SALT + PIN, started at PAIR_SYN
+    let Some(pin_hash) = self.pin_hash.take() else { return };
+    let saltpin_hash = pin_hash.finish();
+    let mut saltpin_hash_w: [u32; LENW_PIN_HASH] = [0; LENW_PIN_HASH];
+    bytes_to_words(&saltpin_hash.check, &mut saltpin_hash_w);
+
+    // 7. Create new FOB_SECRET_ENC by wrapping FOB_SECRET under the key from
+    // the PIN hash
+    let mut nonce: [u8; LEN_AEAD_NONCE] = [0; LEN_AEAD_NONCE];
+    self.rng.fill_bytes(&mut nonce);
+    let secret_enc = wrap(secret, &saltpin_hash.wrap_key, &nonce);
+    let mut secret_enc_w: [u32; LENW_FOB_SECRET_ENC] = [0; LENW_FOB_SECRET_ENC];
+    bytes_to_words(&secret_enc, &mut secret_enc_w);
+
+    // 8. Write to EEPROM
+    self.storage.write(&secret_enc_w, FOBMEM_FOB_SECRET_ENC);
+    self.storage.write(&secret_w, FOBMEM_FOB_SECRET);
+    self.storage.write(&car_id_w, FOBMEM_CAR_ID);
+    self.storage.write(&feature_sig1_w, FOBMEM_FEAT_1_SIG);
+    self.storage.write(&feature_sig2_w, FOBMEM_FEAT_2_SIG);
+    self.storage.write(&feature_sig3_w, FOBMEM_FEAT_3_SIG);
+    self.storage.write(&car_public_w, FOBMEM_CAR_PUBLIC);
+    self.storage.write(&saltpin_hash_w, FOBMEM_PIN_HASH);
+    self.set_pin_fails(0);
+    // Challenges the paired fob has already answered are no good here either
+    self.set_next_chal(u32::from_be_bytes(*next_chal));
+
+    // 9. Set paired flag
+    self.set_paired();
+
+    // log!("Unpaired fob: PAIR transaction completed");
+  }
+
+  /// Report the end of PAIR_SYN to the host
+  fn unpaired_fob_pairing_done(&mut self) {
+    // The key was only good for this pairing
+    self.pair_key = None;
+    self.pin_hash = None;
+    self.leds.set_led(Led::Blue, false);
+    if self.is_paired() {
+      Message::HostSuccess.send(&mut self.host);
+      let until_us = self.clock.now_us() + US_FLASH;
+      self.flash(Led::Green, until_us);
+      self.state = FobState::Idle;
+    } else {
+      // log!("Unpaired fob: Failed to pair");
+      self.host_failure();
+    }
+  }
+
+  /// Handle SW1 button press to unlock car
+  fn request_unlock(&mut self) {
+    log!("Fob: Sending UNLOCK_REQ to car");
+    self.send_to_board(Message::UnlockReq);
+    // Pick the pong now, so answering the car's ping takes no time
+    self.rng.fill_bytes(&mut self.pong);
+    self.deadline_us = self.clock.now_us() + US_CHAL_TIMEOUT;
+    self.state = FobState::AwaitingPing;
+  }
+
+  /// Handle UNLOCK_CHAL
+  fn unlock_respond(&mut self, car_nonce_b: &[u8; LEN_NONCE], car_nonce_sig_b: &[u8; LEN_SIG]) {
+    self.leds.set_led(Led::Blue, true);
+
+    // log!("Fob: Received nonce value: {:x?}", car_nonce_b);
+    log!("Fob: Received nonce signature: {:x?}", car_nonce_sig_b);
+    // Read car public key and car ID from EEPROM
+    let mut car_public_w: [u32; LENW_CAR_PUBLIC] = [0; LENW_CAR_PUBLIC];
+    let mut car_public_b: [u8; LEN_CAR_PUBLIC] = [0; LEN_CAR_PUBLIC];
+    let mut car_id_w: [u32; LENW_CAR_ID] = [0; LENW_CAR_ID];
+    let mut car_id_b: [u8; LEN_CAR_ID] = [0; LEN_CAR_ID];
+    self.storage.read(&mut car_public_w, FOBMEM_CAR_PUBLIC);
+    self.storage.read(&mut car_id_w, FOBMEM_CAR_ID);
+    words_to_bytes(&car_public_w, &mut car_public_b);
+    words_to_bytes(&car_id_w, &mut car_id_b);
+    let car_public = PublicKey::from_untagged_bytes(&car_public_b).unwrap();
+
+    // Verify nonce signature, made over the car ID and everything sent so far
+    let transcript = chal_transcript(&car_id_b, &self.ping, &self.pong, car_nonce_b);
+    let car_nonce_verified: bool = match Signature::from_untagged_bytes(car_nonce_sig_b) {
+      Ok(car_nonce_sig) => car_public.verify(&transcript, &car_nonce_sig),
+      Err(_) => false,
+    };
+    if !car_nonce_verified {
+      log!("Fob: Car nonce signature verification failed");
+      self.leds.set_led(Led::Blue, false);
+      self.send_to_board(Message::UnlockRst);
+      let until_us = self.clock.now_us() + US_FLASH;
+      self.flash(Led::Red, until_us);
+      self.state = FobState::Idle;
+      return;
+    }
+
+    // The car counts its challenges, so anything at or below the last one we
+    // answered was recorded earlier. Refuse it and tell the host
+    let chal_counter_b: [u8; LEN_CHAL_COUNTER] = car_nonce_b[..LEN_CHAL_COUNTER].try_into().unwrap();
+    let chal_counter = u32::from_be_bytes(chal_counter_b);
+    if chal_counter < self.next_chal() {
+      // log!("Fob: Car challenge {} was replayed", chal_counter);
+      self.leds.set_led(Led::Blue, false);
+      self.send_to_board(Message::UnlockRst);
+      Message::HostReplay { chal_counter: chal_counter_b }.send(&mut self.host);
+      let until_us = self.clock.now_us() + US_FLASH;
+      self.flash(Led::Red, until_us);
+      self.state = FobState::Idle;
+      return;
+    }
+    // Never answer this challenge again, even if the power is cut right after
+    self.set_next_chal(chal_counter.saturating_add(1));
+
+    // Pick our own nonce
+    let mut fob_nonce_b: [u8; LEN_NONCE] = [0; LEN_NONCE];
+    self.rng.fill_bytes(&mut fob_nonce_b);
+
+    // Read fob secret key from EEPROM
+    let mut fob_secret_w: [u32; LENW_FOB_SECRET] = [0; LENW_FOB_SECRET];
+    let mut fob_secret_b: [u8; LEN_FOB_SECRET] = [0; LEN_FOB_SECRET];
+    self.storage.read(&mut fob_secret_w, FOBMEM_FOB_SECRET);
+    words_to_bytes(&fob_secret_w, &mut fob_secret_b);
+    let fob_secret = SecretKey::from_bytes(fob_secret_b).unwrap();
+
+    // Use the fob secret key to sign both nonces, the car's signature, the
+    // timing exchange and the car ID
+    let transcript = resp_transcript(&car_id_b, &self.ping, &self.pong, car_nonce_b, car_nonce_sig_b, &fob_nonce_b);
+    // The signing nonce comes from RFC 6979, hedged with random bytes
+    let mut hedge: [u8; LEN_HEDGE] = [0; LEN_HEDGE];
+    self.rng.fill_bytes(&mut hedge);
+    let fob_signed_nonce: [u8; LEN_SIG] = fob_secret.sign_rfc6979(&transcript, &hedge).to_untagged_bytes();
+
+    // Send signed nonce to car
+    // log!("Fob: Sending nonce: {:x?}", fob_nonce_b);
+    // log!("Fob: Sending nonce signature: {:x?}", fob_signed_nonce);
+    self.send_to_board(Message::UnlockResp { nonce: fob_nonce_b, nonce_sig: fob_signed_nonce });
+    self.leds.set_led(Led::Blue, false);
+
+    log!("Fob: Sent UNLOCK_RESP to car");
+    self.deadline_us = self.clock.now_us() + US_GOOD_TIMEOUT;
+    self.state = FobState::AwaitingGood;
+  }
+
+  /// Handle UNLOCK_GOOD
+  fn unlock_send_features(&mut self) {
+    // Read features from EEPROM
+    let mut feature_sig1_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+    let mut feature_sig2_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+    let mut feature_sig3_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+    self.storage.read(&mut feature_sig1_w, FOBMEM_FEAT_1_SIG);
+    self.storage.read(&mut feature_sig2_w, FOBMEM_FEAT_2_SIG);
+    self.storage.read(&mut feature_sig3_w, FOBMEM_FEAT_3_SIG);
+
+    // Convert features to bytes
+    let mut feature_sig1_b: [u8; LEN_SIG] = [0; LEN_SIG];
+    let mut feature_sig2_b: [u8; LEN_SIG] = [0; LEN_SIG];
+    let mut feature_sig3_b: [u8; LEN_SIG] = [0; LEN_SIG];
+    words_to_bytes(&feature_sig1_w, &mut feature_sig1_b);
+    words_to_bytes(&feature_sig2_w, &mut feature_sig2_b);
+    words_to_bytes(&feature_sig3_w, &mut feature_sig3_b);
+
+    // Send UNLOCK_FEAT to car
+    self.send_to_board(Message::UnlockFeat { feature_sigs: [feature_sig1_b, feature_sig2_b, feature_sig3_b] });
+    log!("Fob: Sent UNLOCK_FEAT to car");
+  }
+
+  /// Handle ENAB_FEAT
+  fn enable_feature(&mut self) {
+    // 1. Read in data kept from ENAB_FEAT
+    let feat_num = self.feature_num;
+    let feat_sig = self.feature_sig;
+    // log!("Paired fob: ENAB_FEAT feature number: {:x?}", feat_num);
+    // log!("Paired fob: ENAB_FEAT feature signature: {:x?}", feat_sig);
+
+    // 2. Convert each data element to words
+    let mut feat_num_w: [u32; LENW_FEAT_NUM] = [0; LENW_FEAT_NUM];
+    let mut feat_sig_w: [u32; LENW_FEAT_SIG] = [0; LENW_FEAT_SIG];
+    bytes_to_words(&feat_num, &mut feat_num_w);
+    bytes_to_words(&feat_sig, &mut feat_sig_w);
+
+    // Use as big endian word for comparison
+    let feat_num_w_be: u32 = feat_num_w[0].to_be();
+
+    // 3. Write the feature signature to EEPROM at the provided index
+    if feat_num_w_be == 1 {
+      self.storage.write(&feat_sig_w, FOBMEM_FEAT_1_SIG);
+    } else if feat_num_w_be == 2 {
+      self.storage.write(&feat_sig_w, FOBMEM_FEAT_2_SIG);
+    } else if feat_num_w_be == 3 {
+      self.storage.write(&feat_sig_w, FOBMEM_FEAT_3_SIG);
+    } else {
+      log!("Paired fob: Invalid feature number provided");
+      Message::HostFailure.send(&mut self.host);
+      return;
+    }
+
+    // log!("Paired fob: Feature enabled");
+    Message::HostSuccess.send(&mut self.host);
+  }
+
+  /// Check the paired flag in EEPROM. Returns true if paired, false if unpaired.
+  fn is_paired(&mut self) -> bool {
+    let mut pair_status: [u32; LENW_FOB_IS_PAIRED] = [0; LENW_FOB_IS_PAIRED];
+    self.storage.read(&mut pair_status, FOBMEM_FOB_IS_PAIRED);
+    pair_status[0] != 0
+  }
+
+  /// FOB_SALT from EEPROM.
+  fn fob_salt(&mut self) -> [u8; LEN_FOB_SALT] {
+    let mut salt_w: [u32; LENW_FOB_SALT] = [0; LENW_FOB_SALT];
+    let mut salt: [u8; LEN_FOB_SALT] = [0; LEN_FOB_SALT];
+    self.storage.read(&mut salt_w, FOBMEM_FOB_SALT);
+    words_to_bytes(&salt_w, &mut salt);
+    salt
+  }
+
+  /// The PBKDF2 iteration count stored next to PIN_HASH.
+  fn pin_hash_iterations(&mut self) -> u32 {
+    let mut iters_w: [u32; LENW_PIN_HASH_ITERS] = [0; LENW_PIN_HASH_ITERS];
+    self.storage.read(&mut iters_w, FOBMEM_PIN_HASH_ITERS);
+    u32::from_be_bytes(iters_w[0].to_ne_bytes())
+  }
+
+  /// An unpaired fob that has not yet measured how many PIN hash iterations
+  /// it gets through in time does so now, and keeps the count in
+  /// PIN_HASH_ITERS for when it is paired. A paired fob's count goes with
+  /// its PIN_HASH, so it is left alone.
+  fn calibrate_pin_hash(&mut self) {
+    let iterations = self.pin_hash_iterations();
+    if self.is_paired() || (MIN_PIN_HASH_ITERATIONS..=MAX_PIN_HASH_ITERATIONS).contains(&iterations) {
+      return;
+    }
+    let iterations = calibrate_iterations(&mut self.clock);
+    // log!("Unpaired fob: {} PIN hash iterations", iterations);
+    let iters_w: [u32; LENW_PIN_HASH_ITERS] = [u32::from_ne_bytes(iterations.to_be_bytes())];
+    self.storage.write(&iters_w, FOBMEM_PIN_HASH_ITERS);
+  }
+
+  /// Wrong PINs since the last right one, from EEPROM.
+  fn pin_fails(&mut self) -> u32 {
+    let mut fails_w: [u32; LENW_PIN_FAILS] = [0; LENW_PIN_FAILS];
+    self.storage.read(&mut fails_w, FOBMEM_PIN_FAILS);
+    fails_w[0]
+  }
+
+  /// Store the wrong PIN count in EEPROM.
+  fn set_pin_fails(&mut self, fails: u32) {
+    let fails_w: [u32; LENW_PIN_FAILS] = [fails; LENW_PIN_FAILS];
+    self.storage.write(&fails_w, FOBMEM_PIN_FAILS);
+  }
+
+  /// Read the lowest car challenge counter still answered from EEPROM.
+  fn next_chal(&mut self) -> u32 {
+    let mut next_chal_w: [u32; LENW_NEXT_CHAL] = [0; LENW_NEXT_CHAL];
+    self.storage.read(&mut next_chal_w, FOBMEM_NEXT_CHAL);
+    next_chal_w[0]
+  }
+
+  /// Store the lowest car challenge counter still answered in EEPROM.
+  fn set_next_chal(&mut self, next_chal: u32) {
+    let next_chal_w: [u32; LENW_NEXT_CHAL] = [next_chal; LENW_NEXT_CHAL];
+    self.storage.write(&next_chal_w, FOBMEM_NEXT_CHAL);
+  }
+
+  /// Mix the seed saved on the last boot into the RNG and save a fresh one,
+  /// so a boot with little entropy still does not repeat an earlier one.
+  fn carry_rng_seed(&mut self) {
+    let mut seed_w: [u32; LENW_RNG_SEED] = [0; LENW_RNG_SEED];
+    let mut seed_b: [u8; LEN_RNG_SEED] = [0; LEN_RNG_SEED];
+    self.storage.read(&mut seed_w, FOBMEM_RNG_SEED);
+    words_to_bytes(&seed_w, &mut seed_b);
+    let seed_b = next_rng_seed(&mut self.rng, &seed_b);
+    bytes_to_words(&seed_b, &mut seed_w);
+    self.storage.write(&seed_w, FOBMEM_RNG_SEED);
+  }
+
+  /// Set the paired flag in EEPROM to 1.
+  fn set_paired(&mut self) {
+    let pair_status: [u32; LENW_FOB_IS_PAIRED] = [1; LENW_FOB_IS_PAIRED];
+    self.storage.write(&pair_status, FOBMEM_FOB_IS_PAIRED);
+  }
+}
+
+/// How long PAIR_REQ is refused after this many wrong PINs in a row. An
+/// erased counter reads as the longest lockout.
+fn lockout_us(fails: u32) -> u64 {
+  if fails < FREE_PIN_FAILS {
+    return 0;
+  }
+  let doublings = (fails - FREE_PIN_FAILS).min(US_LOCKOUT_MAX.ilog2());
+  (US_LOCKOUT_BASE << doublings).min(US_LOCKOUT_MAX)
+}
@@ -0,0 +1,194 @@
+//! Framing for the board link. Every message between the car and a fob, or
+//! between two fobs, travels in a frame:
+//!
+//!   SOF | body length (2 bytes) | magic byte | body | CRC-32 (4 bytes)
+//!
+//! Numbers are big endian and the CRC covers everything between the SOF and
+//! the CRC. A receiver only hands on messages from frames that check out, and
+//! after a bad frame it picks up again at the next SOF. The host UART is not
+//! framed so the host tools keep working unchanged.
+
+use crate::protocol::{DecodeError, EncodeError, Message, MAX_MSG_LEN, US_MSG_TIMEOUT};
+use crate::transport::Transport;
+
+/// Start of frame
+pub const SOF:                    u8 = 0x7E;
+
+/// SOF, body length and magic byte.
+pub const LEN_FRAME_HEADER:       usize = 4;
+pub const LEN_FRAME_CRC:          usize = 4;
+
+/// Length of the longest frame.
+pub const MAX_FRAME_LEN:          usize = LEN_FRAME_HEADER + (MAX_MSG_LEN - 1) + LEN_FRAME_CRC;
+
+/// Why a frame was thrown away.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FrameError {
+    /// The body length does not match the message type
+    BadLength { magic: u8, len: usize },
+    /// The CRC does not match the contents of the frame
+    BadCrc,
+    /// The frame holds something that is not a message
+    Decode(DecodeError),
+}
+
+/// CRC-32 (IEEE 802.3, the one used by zlib and Ethernet).
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+impl Message {
+    /// Length of the message in a frame.
+    pub fn frame_len(&self) -> usize {
+        LEN_FRAME_HEADER + (self.encoded_len() - 1) + LEN_FRAME_CRC
+    }
+
+    /// Writes the message in a frame into the start of `buf`. Returns the
+    /// number of bytes written.
+    pub fn encode_frame(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let len = self.frame_len();
+        if buf.len() < len {
+            return Err(EncodeError::BufferTooSmall { needed: len });
+        }
+        let body_len = (self.encoded_len() - 1) as u16;
+        buf[0] = SOF;
+        buf[1..3].copy_from_slice(&body_len.to_be_bytes());
+        self.encode(&mut buf[3..len - LEN_FRAME_CRC])?;
+        let crc = crc32(&buf[1..len - LEN_FRAME_CRC]);
+        buf[len - LEN_FRAME_CRC..len].copy_from_slice(&crc.to_be_bytes());
+        Ok(len)
+    }
+
+    /// Encodes the message in a frame and writes it out.
+    pub fn send_frame(&self, mut transport: impl Transport) {
+        let mut buf: [u8; MAX_FRAME_LEN] = [0; MAX_FRAME_LEN];
+        let len = self.encode_frame(&mut buf).unwrap();
+        transport.write(&buf[..len]);
+    }
+}
+
+/// Picks frames out of the bytes from the board link as they arrive one at a
+/// time.
+pub struct FrameReader {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    started_us: u64,
+}
+
+impl FrameReader {
+    pub const fn new() -> FrameReader {
+        FrameReader { buf: [0; MAX_FRAME_LEN], len: 0, started_us: 0 }
+    }
+
+    /// Adds a byte. Returns the message once a frame that checks out is
+    /// complete, or an error once a frame turns out to be bad. Bytes outside
+    /// of a frame are skipped. A bad frame is not reported if a good one was
+    /// already buffered behind it, that one is returned instead.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Message, FrameError>> {
+        if self.len == 0 && byte != SOF {
+            return None;
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        self.check()
+    }
+
+    /// Adds a byte that arrived at `now_us`, like `push()`, and remembers
+    /// when the frame started so `expire()` can drop it later.
+    pub fn push_at(&mut self, byte: u8, now_us: u64) -> Option<Result<Message, FrameError>> {
+        if self.len == 0 {
+            self.started_us = now_us;
+        }
+        self.push(byte)
+    }
+
+    /// Drops a partly received frame that started more than
+    /// `US_MSG_TIMEOUT` ago. Returns true if one was dropped.
+    pub fn expire(&mut self, now_us: u64) -> bool {
+        if self.len > 0 && now_us >= self.started_us + US_MSG_TIMEOUT {
+            self.len = 0;
+            return true;
+        }
+        false
+    }
+
+    /// Drops a partly received frame.
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+
+    /// Looks at the frames collected so far. After a bad frame the rest of
+    /// the buffer is looked at again, as the next frame may be complete
+    /// already and no more bytes may come to pick it up.
+    fn check(&mut self) -> Option<Result<Message, FrameError>> {
+        let mut first_err = None;
+        loop {
+            match self.check_frame() {
+                Some(Err(err)) => {
+                    first_err.get_or_insert(err);
+                }
+                Some(Ok(msg)) => return Some(Ok(msg)),
+                None => return first_err.map(Err),
+            }
+        }
+    }
+
+    /// Looks at the frame at the start of the buffer.
+    fn check_frame(&mut self) -> Option<Result<Message, FrameError>> {
+        if self.len < LEN_FRAME_HEADER {
+            return None;
+        }
+        let len = u16::from_be_bytes([self.buf[1], self.buf[2]]) as usize;
+        let magic = self.buf[3];
+        match Message::body_len(magic) {
+            Ok(body_len) if body_len == len => {}
+            Ok(_) => {
+                self.skip(1);
+                return Some(Err(FrameError::BadLength { magic, len }));
+            }
+            Err(err) => {
+                self.skip(1);
+                return Some(Err(FrameError::Decode(err)));
+            }
+        }
+
+        let frame_len = LEN_FRAME_HEADER + len + LEN_FRAME_CRC;
+        if self.len < frame_len {
+            return None;
+        }
+        let crc_start = frame_len - LEN_FRAME_CRC;
+        let crc = u32::from_be_bytes(self.buf[crc_start..frame_len].try_into().unwrap());
+        if crc32(&self.buf[1..crc_start]) != crc {
+            // A byte went missing or got mangled. The next frame may already
+            // have started inside this one.
+            self.skip(1);
+            return Some(Err(FrameError::BadCrc));
+        }
+        let msg = Message::decode(&self.buf[3..crc_start]).map_err(FrameError::Decode);
+        self.skip(frame_len);
+        Some(msg)
+    }
+
+    /// Drops the first `n` bytes, and anything after them up to the next SOF.
+    fn skip(&mut self, n: usize) {
+        let start = match self.buf[n..self.len].iter().position(|&byte| byte == SOF) {
+            Some(i) => n + i,
+            None => self.len,
+        };
+        self.buf.copy_within(start..self.len, 0);
+        self.len -= start;
+    }
+}
+
+impl Default for FrameReader {
+    fn default() -> FrameReader {
+        FrameReader::new()
+    }
+}
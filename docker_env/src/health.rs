@@ -0,0 +1,117 @@
+//! Continuous health tests on raw entropy samples (NIST SP 800-90B section
+//! 4.4). They run on every sample before it is hashed, so a source that got
+//! stuck or lost most of its noise is caught instead of being conditioned
+//! into a seed that only looks random.
+//!
+//! Both tests aim for a false alarm rate of 2^-20 for a source that really
+//! has the entropy it was assessed at. The cutoffs are C = 1 + ceil(20 / H)
+//! for the repetition count test and 1 + CRITBINOM(W, 2^-H, 1 - 2^-20) for
+//! the adaptive proportion test with window W.
+
+/**
+ * Temperature sensor: the low byte of every ADC reading, 0.5 bits each
+ */
+pub const TEMP_RCT_CUTOFF:        u32 = 41;
+pub const TEMP_APT_WINDOW:        u32 = 512;
+pub const TEMP_APT_CUTOFF:        u32 = 410;
+
+/**
+ * Tick timer: the low byte of every read, 1 bit each. Only 128 reads are
+ * taken, so the window is all of them
+ */
+pub const TIMER_RCT_CUTOFF:       u32 = 21;
+pub const TIMER_APT_WINDOW:       u32 = 128;
+pub const TIMER_APT_CUTOFF:       u32 = 92;
+
+/// Which test a source failed.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HealthFailure {
+    /// The same sample came up too many times in a row
+    RepetitionCount,
+    /// One sample came up too often within a window
+    AdaptiveProportion,
+}
+
+/// The repetition count and adaptive proportion tests over one source. Once
+/// a test fails, the source stays failed.
+pub struct HealthTests {
+    rct_cutoff: u32,
+    apt_window: u32,
+    apt_cutoff: u32,
+    /// Sample the current run is of, and how long the run is
+    rct_value: u8,
+    rct_count: u32,
+    /// Sample the current window started with, how often it came up and how
+    /// far into the window we are
+    apt_value: u8,
+    apt_count: u32,
+    apt_seen: u32,
+    failure: Option<HealthFailure>,
+}
+
+impl HealthTests {
+    pub fn new(rct_cutoff: u32, apt_window: u32, apt_cutoff: u32) -> Self {
+        HealthTests {
+            rct_cutoff,
+            apt_window,
+            apt_cutoff,
+            rct_value: 0,
+            rct_count: 0,
+            apt_value: 0,
+            apt_count: 0,
+            apt_seen: 0,
+            failure: None,
+        }
+    }
+
+    /// Tests for the temperature sensor.
+    pub fn temp() -> Self {
+        Self::new(TEMP_RCT_CUTOFF, TEMP_APT_WINDOW, TEMP_APT_CUTOFF)
+    }
+
+    /// Tests for the tick timer.
+    pub fn timer() -> Self {
+        Self::new(TIMER_RCT_CUTOFF, TIMER_APT_WINDOW, TIMER_APT_CUTOFF)
+    }
+
+    /// Runs both tests on the next raw sample.
+    pub fn sample(&mut self, x: u8) {
+        // Repetition count test (4.4.1)
+        if self.rct_count > 0 && x == self.rct_value {
+            self.rct_count += 1;
+            if self.rct_count >= self.rct_cutoff {
+                self.fail(HealthFailure::RepetitionCount);
+            }
+        } else {
+            self.rct_value = x;
+            self.rct_count = 1;
+        }
+
+        // Adaptive proportion test (4.4.2)
+        if self.apt_seen == 0 {
+            self.apt_value = x;
+            self.apt_count = 1;
+        } else if x == self.apt_value {
+            self.apt_count += 1;
+            if self.apt_count >= self.apt_cutoff {
+                self.fail(HealthFailure::AdaptiveProportion);
+            }
+        }
+        self.apt_seen += 1;
+        if self.apt_seen == self.apt_window {
+            self.apt_seen = 0;
+        }
+    }
+
+    /// The first test the source failed, if any.
+    pub fn result(&self) -> Result<(), HealthFailure> {
+        match self.failure {
+            Some(failure) => Err(failure),
+            None => Ok(()),
+        }
+    }
+
+    fn fail(&mut self, failure: HealthFailure) {
+        self.failure.get_or_insert(failure);
+    }
+}
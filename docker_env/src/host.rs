@@ -0,0 +1,368 @@
+//! Stand-ins for the board peripherals so the car and fob logic can run on a
+//! regular computer, e.g. in tests.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, TICKS_PER_US};
+use crate::storage::{Storage, EEPROM_SIZE};
+use crate::transport::Transport;
+
+/// One end of an in-memory byte stream, standing in for a UART. Create a
+/// connected pair with `channel_pair()`.
+pub struct Channel {
+    tx: Sender<u8>,
+    rx: Receiver<u8>,
+    peeked: Option<u8>,
+}
+
+/// Creates two connected channel ends. Bytes written to one end can be read
+/// from the other end, like two boards wired together.
+pub fn channel_pair() -> (Channel, Channel) {
+    let (a_tx, b_rx) = channel();
+    let (b_tx, a_rx) = channel();
+    let a = Channel { tx: a_tx, rx: a_rx, peeked: None };
+    let b = Channel { tx: b_tx, rx: b_rx, peeked: None };
+    (a, b)
+}
+
+impl Transport for Channel {
+    fn avail(&mut self) -> bool {
+        if self.peeked.is_none() {
+            self.peeked = self.rx.try_recv().ok();
+        }
+        self.peeked.is_some()
+    }
+
+    fn readb(&mut self) -> u8 {
+        match self.peeked.take() {
+            Some(byte) => byte,
+            None => self.rx.recv().expect("other end of channel was dropped"),
+        }
+    }
+
+    fn writeb(&mut self, data: u8) {
+        // Like a UART, bytes sent to nobody are lost
+        let _ = self.tx.send(data);
+    }
+}
+
+/// A UART carried over TCP, in the style of the eCTF bridges on `ectf-net`.
+/// Bytes sent while nobody is connected are lost, like on a UART with
+/// nothing plugged in.
+pub struct TcpPort {
+    rx: Receiver<u8>,
+    peeked: Option<u8>,
+    stream: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl TcpPort {
+    /// Listens for connections on the address. A new connection replaces the
+    /// previous one, so host tools can connect and disconnect as they please.
+    pub fn listen(addr: impl ToSocketAddrs) -> io::Result<TcpPort> {
+        let listener = TcpListener::bind(addr)?;
+        let (tx, rx) = channel();
+        let stream = Arc::new(Mutex::new(None));
+        let current = stream.clone();
+        thread::spawn(move || {
+            for conn in listener.incoming().flatten() {
+                // Chunks on the board link are tiny, don't hold them back
+                let _ = conn.set_nodelay(true);
+                if let Ok(reader) = conn.try_clone() {
+                    *current.lock().unwrap() = Some(conn);
+                    let tx = tx.clone();
+                    thread::spawn(move || forward(reader, tx));
+                }
+            }
+        });
+        Ok(TcpPort { rx, peeked: None, stream })
+    }
+
+    /// Connects to the address, retrying until something is listening there
+    /// and reconnecting whenever the connection drops.
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<TcpPort> {
+        let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+        let (tx, rx) = channel();
+        let stream = Arc::new(Mutex::new(None));
+        let current = stream.clone();
+        thread::spawn(move || loop {
+            match TcpStream::connect(&addrs[..]).and_then(|conn| Ok((conn.try_clone()?, conn))) {
+                Ok((reader, conn)) => {
+                    let _ = conn.set_nodelay(true);
+                    *current.lock().unwrap() = Some(conn);
+                    forward(reader, tx.clone());
+                    *current.lock().unwrap() = None;
+                }
+                Err(_) => thread::sleep(Duration::from_millis(100)),
+            }
+        });
+        Ok(TcpPort { rx, peeked: None, stream })
+    }
+}
+
+/// Passes bytes from a connection to a channel until the connection closes.
+fn forward(mut reader: TcpStream, tx: Sender<u8>) {
+    let mut buf = [0u8; 256];
+    while let Ok(len @ 1..) = reader.read(&mut buf) {
+        for byte in &buf[..len] {
+            let _ = tx.send(*byte);
+        }
+    }
+}
+
+impl Transport for TcpPort {
+    fn avail(&mut self) -> bool {
+        if self.peeked.is_none() {
+            self.peeked = self.rx.try_recv().ok();
+        }
+        self.peeked.is_some()
+    }
+
+    fn readb(&mut self) -> u8 {
+        match self.peeked.take() {
+            Some(byte) => byte,
+            None => self.rx.recv().expect("TCP reader thread stopped"),
+        }
+    }
+
+    fn writeb(&mut self, data: u8) {
+        self.write(&[data])
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let mut stream = self.stream.lock().unwrap();
+        if let Some(conn) = stream.as_mut() {
+            if conn.write_all(data).is_err() {
+                *stream = None;
+            }
+        }
+    }
+}
+
+/// An EEPROM kept in memory. Starts out as an image from the provisioning
+/// scripts or as erased (all 0xFF) storage.
+#[derive(Clone)]
+pub struct MemStorage {
+    image: [u8; EEPROM_SIZE as usize],
+}
+
+impl MemStorage {
+    /// Creates storage with every byte set to 0xFF, the same starting point
+    /// the provisioning scripts use.
+    pub fn new() -> MemStorage {
+        MemStorage { image: [0xFF; EEPROM_SIZE as usize] }
+    }
+
+    /// Creates storage from an EEPROM image.
+    pub fn from_image(image: [u8; EEPROM_SIZE as usize]) -> MemStorage {
+        MemStorage { image }
+    }
+
+    /// Returns the current EEPROM image.
+    pub fn image(&self) -> &[u8; EEPROM_SIZE as usize] {
+        &self.image
+    }
+
+    /// Returns the current EEPROM image so it can be changed directly.
+    pub fn image_mut(&mut self) -> &mut [u8; EEPROM_SIZE as usize] {
+        &mut self.image
+    }
+}
+
+impl Default for MemStorage {
+    fn default() -> MemStorage {
+        MemStorage::new()
+    }
+}
+
+impl Storage for MemStorage {
+    fn read(&mut self, data: &mut [u32], address: u32) {
+        assert!(address.is_multiple_of(4) && address + data.len() as u32 * 4 <= EEPROM_SIZE);
+        let start = address as usize;
+        for (i, word) in data.iter_mut().enumerate() {
+            let bytes = &self.image[start + i * 4..start + (i + 1) * 4];
+            *word = u32::from_ne_bytes(bytes.try_into().unwrap());
+        }
+    }
+
+    fn write(&mut self, data: &[u32], address: u32) {
+        assert!(address.is_multiple_of(4) && address + data.len() as u32 * 4 <= EEPROM_SIZE);
+        let start = address as usize;
+        for (i, word) in data.iter().enumerate() {
+            self.image[start + i * 4..start + (i + 1) * 4].copy_from_slice(&word.to_ne_bytes());
+        }
+    }
+}
+
+/// An EEPROM image file in the format written by build_car_eeprom.py and
+/// build_fob_eeprom.py. Writes go straight to the file, so like the real
+/// EEPROM they survive a restart.
+pub struct FileStorage {
+    file: File,
+    storage: MemStorage,
+}
+
+impl FileStorage {
+    /// Opens an existing EEPROM image. The file must be exactly 2K.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<FileStorage> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut image = [0u8; EEPROM_SIZE as usize];
+        file.read_exact(&mut image)?;
+        if file.read(&mut [0u8; 1])? != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "EEPROM image is larger than 2K"));
+        }
+        Ok(FileStorage { file, storage: MemStorage::from_image(image) })
+    }
+
+    /// Returns the current EEPROM image.
+    pub fn image(&self) -> &[u8; EEPROM_SIZE as usize] {
+        self.storage.image()
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&mut self, data: &mut [u32], address: u32) {
+        self.storage.read(data, address)
+    }
+
+    fn write(&mut self, data: &[u32], address: u32) {
+        self.storage.write(data, address);
+        let start = address as usize;
+        let end = start + data.len() * 4;
+        self.file.seek(SeekFrom::Start(address as u64))
+            .and_then(|_| self.file.write_all(&self.storage.image()[start..end]))
+            .and_then(|_| self.file.flush())
+            .expect("failed to write EEPROM image");
+    }
+}
+
+/// A clock that only moves when it is advanced, either by the code using it
+/// (sleeping or waiting on the delay timer takes no real time) or by calling
+/// `advance_us()`. Clones share the same time, so a test can keep a clone to
+/// check or move the time of the code under test.
+#[derive(Clone, Default)]
+pub struct VirtualClock {
+    state: Arc<Mutex<VirtualTime>>,
+}
+
+#[derive(Default)]
+struct VirtualTime {
+    now_us: u64,
+    delay_timer_end_us: Option<u64>,
+}
+
+impl VirtualClock {
+    /// Creates a clock starting at time zero.
+    pub fn new() -> VirtualClock {
+        VirtualClock::default()
+    }
+
+    /// Returns the microseconds elapsed since the clock was created.
+    pub fn now_us(&self) -> u64 {
+        self.state.lock().unwrap().now_us
+    }
+
+    /// Moves the clock forward.
+    pub fn advance_us(&self, us: u64) {
+        self.state.lock().unwrap().now_us += us;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn sleep_us(&mut self, us: u32) {
+        assert_ne!(us, 0);
+        self.advance_us(us as u64);
+    }
+
+    fn start_delay_timer_us(&mut self, us: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.delay_timer_end_us = Some(state.now_us + us as u64);
+    }
+
+    fn wait_delay_timer(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        // The board would wait forever for a delay timer that was never started
+        let end_us = state.delay_timer_end_us.take().expect("delay timer is not running");
+        state.now_us = state.now_us.max(end_us);
+    }
+
+    fn get_remaining_us_delay_timer(&mut self) -> u32 {
+        let state = self.state.lock().unwrap();
+        match state.delay_timer_end_us {
+            Some(end_us) => end_us.saturating_sub(state.now_us) as u32,
+            None => 0,
+        }
+    }
+
+    fn get_tick_timer(&mut self) -> u64 {
+        self.state.lock().unwrap().now_us * TICKS_PER_US
+    }
+
+    fn wait_remaining_us_delay_timer(&mut self, us: u32) {
+        let remaining_us = self.get_remaining_us_delay_timer();
+        if remaining_us > us {
+            self.advance_us((remaining_us - us) as u64);
+        }
+    }
+}
+
+/// A clock that follows real time, for running the car and fob in real time
+/// off the board.
+pub struct WallClock {
+    start: Instant,
+    delay_timer_end: Option<Instant>,
+}
+
+impl WallClock {
+    /// Creates a clock whose tick timer starts now.
+    pub fn new() -> WallClock {
+        WallClock { start: Instant::now(), delay_timer_end: None }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> WallClock {
+        WallClock::new()
+    }
+}
+
+impl Clock for WallClock {
+    fn sleep_us(&mut self, us: u32) {
+        assert_ne!(us, 0);
+        thread::sleep(Duration::from_micros(us as u64));
+    }
+
+    fn start_delay_timer_us(&mut self, us: u32) {
+        self.delay_timer_end = Some(Instant::now() + Duration::from_micros(us as u64));
+    }
+
+    fn wait_delay_timer(&mut self) {
+        // The board would wait forever for a delay timer that was never started
+        let end = self.delay_timer_end.take().expect("delay timer is not running");
+        thread::sleep(end.saturating_duration_since(Instant::now()));
+    }
+
+    fn get_remaining_us_delay_timer(&mut self) -> u32 {
+        match self.delay_timer_end {
+            Some(end) => end.saturating_duration_since(Instant::now()).as_micros() as u32,
+            None => 0,
+        }
+    }
+
+    fn get_tick_timer(&mut self) -> u64 {
+        self.start.elapsed().as_micros() as u64 * TICKS_PER_US
+    }
+
+    fn wait_remaining_us_delay_timer(&mut self, us: u32) {
+        let remaining_us = self.get_remaining_us_delay_timer();
+        if remaining_us > us {
+            thread::sleep(Duration::from_micros((remaining_us - us) as u64));
+        }
+    }
+}
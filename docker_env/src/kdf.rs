@@ -0,0 +1,112 @@
+//! HMAC-SHA256 (RFC 2104) and PBKDF2-HMAC-SHA256 (RFC 8018), for turning
+//! a PIN into a key that takes real work to guess.
+
+use sha2::{Digest, Sha256};
+
+pub const LEN_MAC:                usize = 32;
+const LEN_BLOCK:                  usize = 64;
+
+/// HMAC-SHA256 with the key already mixed into both hash states, so
+/// cloning it is cheaper than keying it again.
+#[derive(Clone)]
+pub struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> HmacSha256 {
+        let mut block = [0u8; LEN_BLOCK];
+        if key.len() > LEN_BLOCK {
+            block[..LEN_MAC].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha256::new();
+        let mut outer = Sha256::new();
+        inner.update(block.map(|b| b ^ 0x36));
+        outer.update(block.map(|b| b ^ 0x5C));
+        HmacSha256 { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finish(self) -> [u8; LEN_MAC] {
+        let mut outer = self.outer;
+        outer.update(self.inner.finalize());
+        outer.finalize().into()
+    }
+}
+
+/// HMAC-SHA256 of one message.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; LEN_MAC] {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.finish()
+}
+
+/// One 32 byte block of PBKDF2-HMAC-SHA256, worked out a few iterations at
+/// a time so the caller can keep doing other things in between.
+#[derive(Clone)]
+pub struct Pbkdf2 {
+    keyed: HmacSha256,
+    u: [u8; LEN_MAC],
+    t: [u8; LEN_MAC],
+    left: u32,
+}
+
+impl Pbkdf2 {
+    pub fn new(password: &[u8], salt: &[u8], iterations: u32) -> Pbkdf2 {
+        Pbkdf2::block(HmacSha256::new(password), &[salt], iterations, 1)
+    }
+
+    /// Same as `new()` with `label || salt` as the salt, which keeps keys
+    /// derived from the same password for different uses apart.
+    pub fn labelled(password: &[u8], label: &[u8], salt: &[u8], iterations: u32) -> Pbkdf2 {
+        Pbkdf2::block(HmacSha256::new(password), &[label, salt], iterations, 1)
+    }
+
+    fn block(keyed: HmacSha256, salt: &[&[u8]], iterations: u32, index: u32) -> Pbkdf2 {
+        let mut mac = keyed.clone();
+        for part in salt {
+            mac.update(part);
+        }
+        mac.update(&index.to_be_bytes());
+        let u = mac.finish();
+        Pbkdf2 { keyed, u, t: u, left: iterations.saturating_sub(1) }
+    }
+
+    /// Runs up to `iterations` more iterations. Returns true once all of
+    /// them are done.
+    pub fn step(&mut self, iterations: u32) -> bool {
+        let n = iterations.min(self.left);
+        for _ in 0..n {
+            let mut mac = self.keyed.clone();
+            mac.update(&self.u);
+            self.u = mac.finish();
+            for (t, u) in self.t.iter_mut().zip(self.u) {
+                *t ^= u;
+            }
+        }
+        self.left -= n;
+        self.left == 0
+    }
+
+    /// Runs whatever iterations are left and returns the result.
+    pub fn finish(mut self) -> [u8; LEN_MAC] {
+        self.step(self.left);
+        self.t
+    }
+}
+
+/// Fills `out` with PBKDF2-HMAC-SHA256 of `password` and `salt`.
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    let keyed = HmacSha256::new(password);
+    for (i, chunk) in out.chunks_mut(LEN_MAC).enumerate() {
+        let t = Pbkdf2::block(keyed.clone(), &[salt], iterations, i as u32 + 1).finish();
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
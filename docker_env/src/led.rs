@@ -0,0 +1,24 @@
+#[derive(PartialEq, Clone, Copy, Debug)]
+/// The Launchpad has a tri-colour LED, which we consider
+/// to be three separate LEDs.
+pub enum Led {
+    /// The Red LED
+    Red,
+    /// The Blue LED
+    Blue,
+    /// The Green LED
+    Green,
+}
+
+/// Shows car and fob status. On the board these are the Launchpad LEDs (see
+/// tiva/board.rs).
+pub trait Leds {
+    /// Turns an LED on or off.
+    fn set_led(&mut self, led: Led, on: bool);
+}
+
+impl<T: Leds> Leds for &mut T {
+    fn set_led(&mut self, led: Led, on: bool) {
+        (**self).set_led(led, on)
+    }
+}
@@ -1,19 +1,50 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "board")]
 pub mod tiva;
+#[cfg(feature = "board")]
 pub mod driverlib;
+pub mod aead;
+pub mod car;
+pub mod clock;
+pub mod fob;
+pub mod frame;
+pub mod health;
+#[cfg(feature = "std")]
+pub mod host;
+pub mod kdf;
+pub mod led;
+pub mod link;
+pub mod pin;
+pub mod protocol;
+pub mod rfc6979;
+pub mod rng;
+pub mod storage;
+pub mod transport;
+pub mod wrap;
 
-use core::{slice, array::from_fn};
+#[cfg(feature = "board")]
+use core::{array::from_fn, mem::{size_of, MaybeUninit}, ptr};
+use core::sync::atomic::{AtomicU8, Ordering};
 
-use driverlib::{get_temp_samples, get_tick_timer};
+#[cfg(feature = "board")]
+use driverlib::get_temp_samples;
+use health::{HealthFailure, HealthTests};
 use p256_cortex_m4::{SecretKey, Signature, PublicKey};
 use rand_chacha::rand_core::{CryptoRng, RngCore};
+use rfc6979::Rfc6979;
 use sha2::{Digest, Sha256};
+#[cfg(feature = "board")]
 pub use tiva::board::Board;
+pub use clock::Clock;
+pub use led::{Led, Leds};
+pub use storage::Storage;
+pub use transport::{Port, Transport};
 
 /// Sets up the Tiva development board. This includes setting up all the
 /// peripherals we use for eCTF, including EEPROM, UART, and GPIO.
 /// See wrapper.c for more information.
+#[cfg(feature = "board")]
 pub fn setup_board() -> Board {
     let board = Board::new();
     driverlib::init_system();
@@ -22,8 +53,8 @@ pub fn setup_board() -> Board {
 
 /// Converts an array of u8 to an array of u32
 pub fn bytes_to_words(bytes: &[u8], words: &mut [u32]) {
-    assert!(bytes.len() %4 == 0 && words.len() *4 == bytes.len());
-    if bytes.len() % 4 == 0 && words.len() *4 == bytes.len() {
+    assert!(bytes.len().is_multiple_of(4) && words.len() *4 == bytes.len());
+    if bytes.len().is_multiple_of(4) && words.len() *4 == bytes.len() {
         for i in 0..words.len() {
             words[i] = u32::from_ne_bytes(bytes[i * 4..(i + 1) *  4].try_into().unwrap());
         }
@@ -32,8 +63,8 @@ pub fn bytes_to_words(bytes: &[u8], words: &mut [u32]) {
 
 /// Converts an array of u32 to an array of u8
 pub fn words_to_bytes(words: &[u32], bytes: &mut [u8]) {
-    assert!(bytes.len() %4 == 0 && words.len() *4 == bytes.len());
-    if bytes.len() % 4 == 0 && words.len() *4 == bytes.len() {
+    assert!(bytes.len().is_multiple_of(4) && words.len() *4 == bytes.len());
+    if bytes.len().is_multiple_of(4) && words.len() *4 == bytes.len() {
         for i in 0..words.len() {
             let word_bytes = words[i].to_ne_bytes();
             bytes[i * 4] = word_bytes[0];
@@ -46,6 +77,7 @@ pub fn words_to_bytes(words: &[u32], bytes: &mut [u8]) {
 
 /// Pass directly to hprintln if we are not in debug mode. Otherwise, do
 /// nothing.
+#[cfg(feature = "board")]
 #[macro_export]
 macro_rules! log {
     ($($arg:tt)*) => {
@@ -56,49 +88,213 @@ macro_rules! log {
     }
 }
 
-/// Reads all of SRAM and hashes it to get a 32-byte entropy value.
-pub fn get_ram_entropy() -> [u8; 32] {
-    let memory: &[u8];
-    unsafe {
-        // maybe is secure?
-        memory = slice::from_raw_parts(0x20000000 as *const u8, 0x00008000);
+/// There is no semihosting off the board, so only check the format arguments.
+#[cfg(not(feature = "board"))]
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            let _ = format_args!($($arg)*);
+        }
+    }
+}
+
+/// Hash of SRAM as it came up at power on. It lives in `.uninit`, which the
+/// runtime does not zero, so it survives until `get_ram_entropy()` reads it.
+#[cfg(feature = "board")]
+#[link_section = ".uninit.POWER_ON_RAM"]
+static mut POWER_ON_RAM: MaybeUninit<[u8; 32]> = MaybeUninit::uninit();
+
+/// SRAM, as laid out in memory.x.
+#[cfg(feature = "board")]
+const SRAM_START: usize = 0x20000000;
+#[cfg(feature = "board")]
+const SRAM_END: usize = 0x20008000;
+/// Stack left out below `capture_ram_entropy()`'s frame, for the hashing to
+/// run in.
+#[cfg(feature = "board")]
+const STACK_RESERVE: usize = 0x1000;
+
+/// Hashes SRAM before the runtime writes to it, while it still holds
+/// whatever the cells settled to at power on. Call this from a `#[pre_init]`
+/// function; by `main()` the zeroed `.bss`, the copied `.data` and the board
+/// setup have made most of SRAM predictable. The stack in use and the hash
+/// itself change while SRAM is read, so they are left out.
+///
+/// # Safety
+///
+/// Must only be called from `#[pre_init]`, before RAM is initialized.
+#[cfg(feature = "board")]
+pub unsafe fn capture_ram_entropy() {
+    // Most cells power up the same way every time, but a few percent settle
+    // on thermal noise. Hashing all of them keeps those bits, and the result
+    // is only ever combined with the other sources, never used alone
+    let mut chunk: [u8; 64] = [0; 64];
+    let stack_low = (ptr::addr_of!(chunk) as usize).saturating_sub(STACK_RESERVE).clamp(SRAM_START, SRAM_END);
+    let output = ptr::addr_of!(POWER_ON_RAM) as usize;
+    let output = output..output + size_of::<[u8; 32]>();
+    // SRAM belongs to no Rust object yet, so read it a byte at a time through
+    // raw pointers rather than as a slice
+    let mut hash = Sha256::new();
+    let mut address = SRAM_START;
+    while address < stack_low {
+        let len = chunk.len().min(stack_low - address);
+        for (byte, address) in chunk[..len].iter_mut().zip(address..) {
+            *byte = if output.contains(&address) { 0 } else { ptr::read_volatile(address as *const u8) };
+        }
+        hash.update(&chunk[..len]);
+        address += len;
     }
-    sha256(memory)
+    (*ptr::addr_of_mut!(POWER_ON_RAM)).write(hash.finalize().into());
+}
+
+/// Gets the hash of SRAM from power on, as captured by
+/// `capture_ram_entropy()`.
+#[cfg(feature = "board")]
+pub fn get_ram_entropy() -> [u8; 32] {
+    unsafe { (*ptr::addr_of!(POWER_ON_RAM)).assume_init() }
 }
 
 /// Gets 1024 samples from the temperature sensor and hashes them to get a
-/// 32-byte entropy value.
-pub fn get_temp_entropy() -> [u8; 32] {
+/// 32-byte entropy value. Fails if the samples do not pass the health tests.
+/// When each sample was taken goes into `pool`.
+#[cfg(feature = "board")]
+pub fn get_temp_entropy(pool: &mut EntropyPool) -> Result<[u8; 32], HealthFailure> {
     let mut samples = [0u32; 8];
     let mut samples_lsb;
+    let mut health = HealthTests::temp();
     let mut hash = Sha256::new();
     for _ in 0..1024 {
         get_temp_samples(&mut samples);
+        pool.add(Event::AdcSample, driverlib::get_tick_timer());
         samples_lsb = samples.map(|x| x as u8);
+        for sample in samples_lsb {
+            health.sample(sample);
+        }
         hash.update(samples_lsb);
     }
-    hash.finalize().into()
+    health.result()?;
+    Ok(hash.finalize().into())
 }
 
 /// Gets 128 samples from the tick timer and hashes them to get a 32-byte
-/// entropy value.
-pub fn get_timer_entropy() -> [u8; 32] {
+/// entropy value. Fails if the samples do not pass the health tests.
+pub fn get_timer_entropy(clock: &mut impl Clock) -> Result<[u8; 32], HealthFailure> {
+    let mut health = HealthTests::timer();
     let mut hash = Sha256::new();
     for _ in 0..128 {
-        hash.update(get_tick_timer().to_ne_bytes())
+        let ticks = clock.get_tick_timer();
+        health.sample(ticks as u8);
+        hash.update(ticks.to_ne_bytes())
     }
-    hash.finalize().into()
+    health.result()?;
+    Ok(hash.finalize().into())
+}
+
+/**
+ * Entropy sources, as bits of `failed_entropy_sources()`
+ */
+pub const ENTROPY_TEMP:           u8 = 1 << 0;
+pub const ENTROPY_TIMER:          u8 = 1 << 1;
+
+/// Sources that have failed a health test since startup.
+static FAILED_SOURCES: AtomicU8 = AtomicU8::new(0);
+
+/// Which entropy sources `get_combined_entropy()` has had to leave out since
+/// startup. Zero if they all passed.
+pub fn failed_entropy_sources() -> u8 {
+    FAILED_SOURCES.load(Ordering::Relaxed)
 }
 
 /// Combines the entropy from the RAM, temperature sensor, and tick timer to
-/// get a 32-byte entropy value.
-pub fn get_combined_entropy() -> [u8; 32] {
+/// get a 32-byte entropy value. A source that fails its health tests is left
+/// out and recorded in `failed_entropy_sources()`.
+#[cfg(feature = "board")]
+pub fn get_combined_entropy(pool: &mut EntropyPool) -> [u8; 32] {
     let ram_entropy = get_ram_entropy();
-    let temp_entropy = get_temp_entropy();
-    let timer_entropy = get_timer_entropy();
+    let temp_entropy = get_temp_entropy(pool).unwrap_or_else(|failure| {
+        log!("Entropy: Temperature sensor failed {:?}", failure);
+        FAILED_SOURCES.fetch_or(ENTROPY_TEMP, Ordering::Relaxed);
+        [0; 32]
+    });
+    let timer_entropy = get_timer_entropy(&mut driverlib::Timers).unwrap_or_else(|failure| {
+        log!("Entropy: Tick timer failed {:?}", failure);
+        FAILED_SOURCES.fetch_or(ENTROPY_TIMER, Ordering::Relaxed);
+        [0; 32]
+    });
     from_fn(|i| ram_entropy[i] ^ temp_entropy[i] ^ timer_entropy[i])
 }
 
+/**
+ * Entropy estimates for events, in eighths of a bit
+ */
+pub const EVENT_ENTROPY_BUTTON:   u32 = 64; // a person pressing SW1 is off by thousands of ticks
+pub const EVENT_ENTROPY_UART:     u32 = 1; // only jitter in when a byte lands, the sender picks the rest
+pub const EVENT_ENTROPY_ADC:      u32 = 0; // conversions are timed by the ADC clock, so none at all
+const MAX_POOL_ENTROPY:           u32 = 256 * 8; // no more than the hash can hold
+
+/// Things that happen at times nobody can predict to the tick.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Event {
+    /// SW1 was pressed
+    Button,
+    /// A byte came in on one of the UARTs. Whoever sent it chose the value,
+    /// so only the port is kept
+    UartByte(Port),
+    /// The temperature sensor was sampled
+    AdcSample,
+}
+
+impl Event {
+    /// How much entropy the time of one of these is assumed to hold, in
+    /// eighths of a bit.
+    pub fn entropy(&self) -> u32 {
+        match self {
+            Event::Button => EVENT_ENTROPY_BUTTON,
+            Event::UartByte(..) => EVENT_ENTROPY_UART,
+            Event::AdcSample => EVENT_ENTROPY_ADC,
+        }
+    }
+}
+
+/// Collects the tick timer value at every event, hashed together, along with
+/// an estimate of how much entropy that adds up to. The RNG empties it when
+/// it reseeds.
+#[derive(Clone, Default)]
+pub struct EntropyPool {
+    hash: Sha256,
+    entropy: u32,
+}
+
+impl EntropyPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an event that happened at `ticks`.
+    pub fn add(&mut self, event: Event, ticks: u64) {
+        match event {
+            Event::Button => self.hash.update([0]),
+            Event::UartByte(port) => self.hash.update([1, port as u8]),
+            Event::AdcSample => self.hash.update([2]),
+        }
+        self.hash.update(ticks.to_be_bytes());
+        self.entropy = (self.entropy + event.entropy()).min(MAX_POOL_ENTROPY);
+    }
+
+    /// Estimated entropy in the pool, in whole bits.
+    pub fn entropy_bits(&self) -> u32 {
+        self.entropy / 8
+    }
+
+    /// Empties the pool, giving back the hash of everything added since it
+    /// was last emptied.
+    pub fn take(&mut self) -> [u8; 32] {
+        self.entropy = 0;
+        core::mem::take(&mut self.hash).finalize().into()
+    }
+}
+
 /// Hashes a message using SHA-256.
 /// https://github.com/ycrypto/p256-cortex-m4/blob/290b275c08ef8964eda308ea56c888c1cf0fa06a/src/lib.rs#L27-L33
 pub fn sha256(message: &[u8]) -> [u8; 32] {
@@ -108,9 +304,24 @@ pub fn sha256(message: &[u8]) -> [u8; 32] {
     data.into()
 }
 
+/// Compares two byte strings in time that depends only on their lengths, so
+/// a mismatch does not give away how many bytes were right.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y));
+    // Keep the compiler from turning this back into an early exit
+    core::hint::black_box(diff) == 0
+}
+
 /// Signs a message using the ECDSA algorithm.
 pub trait Signer {
     fn sign(&self, message: &[u8], rng: impl CryptoRng + RngCore) -> Signature;
+
+    /// Signs with an RFC 6979 nonce, hedged with `extra` if it is not empty.
+    /// Safe even if the bytes in `extra` are not random at all.
+    fn sign_rfc6979(&self, message: &[u8], extra: &[u8]) -> Signature;
 }
 
 /// Implementation of signature generation using the ECDSA algorithm.
@@ -120,6 +331,15 @@ impl Signer for SecretKey {
         let prehashed_message = sha256(message);
         self.sign_prehashed(prehashed_message.as_ref(), rng)
     }
+
+    fn sign_rfc6979(&self, message: &[u8], extra: &[u8]) -> Signature {
+        let prehashed_message = sha256(message);
+        // Unsafe only in that the copy has to stay secret. It goes nowhere but
+        // the nonce generator
+        let secret = unsafe { self.to_bytes() };
+        let nonces = Rfc6979::new(&secret, &prehashed_message, extra);
+        self.sign_prehashed(prehashed_message.as_ref(), nonces)
+    }
 }
 
 /// Verifies a signature using the ECDSA algorithm.
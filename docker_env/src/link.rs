@@ -0,0 +1,229 @@
+//! Reliable delivery over the board link. Received bytes wait in a buffer
+//! filled by the UART interrupt handler, but a byte can still be mangled on
+//! the wire or dropped when that buffer overflows. So every message is cut
+//! into chunks of `LEN_CHUNK` bytes, each sent in its own `LINK_DATA` frame
+//! (which fits in the UART FIFO). The next chunk only goes out once the other
+//! side has answered with a `LINK_ACK` for this one, and a chunk that is not
+//! acknowledged in time is sent again, up to `MAX_TRIES` times.
+
+use crate::frame::FrameReader;
+use crate::protocol::{Message, MsgReader, LEN_CHUNK, MAX_MSG_LEN};
+use crate::transport::Transport;
+
+/**
+ * Timing
+ */
+pub const US_ACK_TIMEOUT:         u64 = 100_000; // resend a chunk after this long
+pub const MAX_TRIES:              u8 = 5; // then drop everything queued
+const US_RETRY_WINDOW:            u64 = MAX_TRIES as u64 * US_ACK_TIMEOUT; // every copy of a chunk arrives within this
+
+/// Room for a message in flight and one queued behind it.
+const TX_QUEUE_LEN:               usize = 2 * MAX_MSG_LEN;
+const TX_QUEUE_MSGS:              usize = 4;
+
+/// Why a message was not queued.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LinkError {
+    /// Too much is still waiting to be sent
+    QueueFull,
+}
+
+/// One side of the board link. Feed it every byte from the board UART with
+/// `on_byte()` and call `on_tick()` regularly so it can resend chunks.
+pub struct Link {
+    frame_rx: FrameReader,
+    msg_rx: MsgReader,
+    rx_seq: Option<u8>,
+    rx_chunk: (u8, [u8; LEN_CHUNK]),
+    rx_at_us: u64,
+    tx_buf: [u8; TX_QUEUE_LEN],
+    tx_len: usize,
+    tx_ends: [usize; TX_QUEUE_MSGS],
+    tx_msgs: usize,
+    tx_acked: usize,
+    tx_chunk: Option<usize>,
+    tx_seq: u8,
+    tx_restart: bool,
+    tries: u8,
+    resend_at_us: u64,
+    now_us: u64,
+}
+
+impl Link {
+    pub const fn new() -> Link {
+        Link {
+            frame_rx: FrameReader::new(),
+            msg_rx: MsgReader::new(),
+            rx_seq: None,
+            rx_chunk: (0, [0; LEN_CHUNK]),
+            rx_at_us: 0,
+            tx_buf: [0; TX_QUEUE_LEN],
+            tx_len: 0,
+            tx_ends: [0; TX_QUEUE_MSGS],
+            tx_msgs: 0,
+            tx_acked: 0,
+            tx_chunk: None,
+            tx_seq: 0,
+            tx_restart: true,
+            tries: 0,
+            resend_at_us: 0,
+            now_us: 0,
+        }
+    }
+
+    /// Queues a message, and starts sending it if nothing else is in flight.
+    pub fn send(&mut self, msg: &Message, transport: impl Transport) -> Result<(), LinkError> {
+        let len = msg.encoded_len();
+        if self.tx_msgs == TX_QUEUE_MSGS || self.tx_len + len > TX_QUEUE_LEN {
+            return Err(LinkError::QueueFull);
+        }
+        msg.encode(&mut self.tx_buf[self.tx_len..]).unwrap();
+        self.tx_len += len;
+        self.tx_ends[self.tx_msgs] = self.tx_len;
+        self.tx_msgs += 1;
+        self.next_chunk(transport);
+        Ok(())
+    }
+
+    /// True if nothing is queued or waiting for an acknowledgement.
+    pub fn idle(&self) -> bool {
+        self.tx_msgs == 0
+    }
+
+    /// Handles a byte from the board UART. Returns a message once its last
+    /// chunk has arrived.
+    pub fn on_byte(&mut self, byte: u8, now_us: u64, mut transport: impl Transport) -> Option<Message> {
+        self.now_us = now_us;
+        match self.frame_rx.push_at(byte, now_us)? {
+            Ok(Message::LinkAck { seq }) => {
+                self.on_ack(seq, transport);
+                None
+            }
+            Ok(Message::LinkData { seq, restart, len, data }) if len as usize <= LEN_CHUNK => {
+                // Acknowledge every copy, in case an earlier LINK_ACK was lost
+                Message::LinkAck { seq }.send_frame(&mut transport);
+                if self.is_duplicate(seq, restart, (len, data)) {
+                    return None;
+                }
+                self.rx_seq = Some(seq);
+                self.rx_chunk = (len, data);
+                self.rx_at_us = now_us;
+                if restart {
+                    self.msg_rx.reset();
+                }
+                // Chunks never span two messages, so only the last byte can
+                // finish one
+                let mut received = None;
+                for byte in &data[..len as usize] {
+                    if let Some(Ok(msg)) = self.msg_rx.push(*byte) {
+                        received = Some(msg);
+                    }
+                }
+                received
+            }
+            // Bad frames, and messages that were not sent through a link
+            _ => None,
+        }
+    }
+
+    /// Handles the passing of time. Resends a chunk that was not
+    /// acknowledged in time, or gives up on everything queued after
+    /// `MAX_TRIES`.
+    pub fn on_tick(&mut self, now_us: u64, transport: impl Transport) {
+        self.now_us = now_us;
+        self.frame_rx.expire(now_us);
+        if self.tx_chunk.is_some() && now_us >= self.resend_at_us {
+            if self.tries < MAX_TRIES {
+                self.transmit(transport);
+            } else {
+                self.drop_tx();
+            }
+        }
+    }
+
+    /// Drops everything queued and anything partly received, e.g. when a
+    /// transaction is aborted.
+    pub fn reset(&mut self) {
+        self.drop_tx();
+        self.frame_rx.reset();
+        self.msg_rx.reset();
+    }
+
+    /// True if a chunk is another copy of the last one received. A rebooted
+    /// sender starts over at the same sequence number, but with the restart
+    /// flag set, so a restart chunk only counts as a copy if it is the same
+    /// chunk and came in while the sender could still be retrying it.
+    fn is_duplicate(&self, seq: u8, restart: bool, chunk: (u8, [u8; LEN_CHUNK])) -> bool {
+        if self.rx_seq != Some(seq) {
+            return false;
+        }
+        !restart || (chunk == self.rx_chunk && self.now_us < self.rx_at_us + US_RETRY_WINDOW)
+    }
+
+    /// Starts sending the next chunk, unless one is already in flight.
+    fn next_chunk(&mut self, transport: impl Transport) {
+        if self.tx_chunk.is_some() || self.tx_msgs == 0 {
+            return;
+        }
+        let end = self.tx_ends[0].min(self.tx_acked + LEN_CHUNK);
+        self.tx_chunk = Some(end - self.tx_acked);
+        self.tries = 0;
+        self.transmit(transport);
+    }
+
+    /// Sends the chunk in flight (again).
+    fn transmit(&mut self, transport: impl Transport) {
+        let len = self.tx_chunk.unwrap();
+        let mut data: [u8; LEN_CHUNK] = [0; LEN_CHUNK];
+        data[..len].copy_from_slice(&self.tx_buf[self.tx_acked..self.tx_acked + len]);
+        Message::LinkData { seq: self.tx_seq, restart: self.tx_restart, len: len as u8, data }.send_frame(transport);
+        self.tries += 1;
+        self.resend_at_us = self.now_us + US_ACK_TIMEOUT;
+    }
+
+    /// Moves on to the next chunk once the one in flight is acknowledged.
+    fn on_ack(&mut self, seq: u8, transport: impl Transport) {
+        let Some(len) = self.tx_chunk else {
+            return;
+        };
+        if seq != self.tx_seq {
+            return;
+        }
+        self.tx_chunk = None;
+        self.tx_seq = self.tx_seq.wrapping_add(1);
+        self.tx_restart = false;
+        self.tx_acked += len;
+        if self.tx_acked == self.tx_ends[0] {
+            // Whole message delivered
+            let sent = self.tx_acked;
+            self.tx_buf.copy_within(sent..self.tx_len, 0);
+            self.tx_len -= sent;
+            self.tx_ends.copy_within(1..self.tx_msgs, 0);
+            self.tx_msgs -= 1;
+            for end in &mut self.tx_ends[..self.tx_msgs] {
+                *end -= sent;
+            }
+            self.tx_acked = 0;
+        }
+        self.next_chunk(transport);
+    }
+
+    /// Forgets everything queued. The other side may or may not have the
+    /// chunk in flight, so start over with a new sequence number and let it
+    /// know to drop whatever it has of the message.
+    fn drop_tx(&mut self) {
+        self.tx_len = 0;
+        self.tx_msgs = 0;
+        self.tx_acked = 0;
+        if self.tx_chunk.take().is_some() {
+            self.tx_seq = self.tx_seq.wrapping_add(1);
+        }
+        self.tx_restart = true;
+    }
+}
+
+impl Default for Link {
+    fn default() -> Link {
+        Link::new()
+    }
+}
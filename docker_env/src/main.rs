@@ -1,14 +1,20 @@
 #![no_std]
 #![no_main]
 
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, pre_init};
 use embedded_hal::digital::v2::OutputPin;
 
 use tiva::{
     driverlib::{self, eeprom_read, eeprom_write, start_delay_timer_us, sleep_us, wait_delay_timer, get_tick_timer, get_temp_samples, get_remaining_us_delay_timer},
-    log, setup_board, sha256, Board, Signer, Verifier, get_combined_entropy
+    log, setup_board, capture_ram_entropy, sha256, Board, Signer, Verifier, EntropyPool, failed_entropy_sources, get_combined_entropy
 };
 
+// Hash SRAM before the runtime sets it up
+#[pre_init]
+unsafe fn before_init() {
+    capture_ram_entropy();
+}
+
 /// This code is not utilized by the final device code. It is used as a test
 /// playgroud.
 
@@ -110,10 +116,13 @@ fn eeprom_example() {
 
 fn entropy_example() {
     write_str_to_host("Begin gathering entropy\n");
-    let entropy = get_combined_entropy();
+    let entropy = get_combined_entropy(&mut EntropyPool::new());
     write_str_to_host("entropy: ");
     write_to_hex(&entropy);
     write_str_to_host("\n");
+    write_str_to_host("failed sources: ");
+    write_to_hex(&[failed_entropy_sources()]);
+    write_str_to_host("\n");
     write_str_to_host("Temp example samples:\n");
     let mut samples = [0u32; 8];
     for _ in 0..10 {
@@ -0,0 +1,103 @@
+//! Slow, salted PIN hashes. Anyone who dumps a fob's EEPROM has the hash
+//! and the salt, and a 3 byte PIN has only 16 million values, so every guess
+//! has to cost a full PBKDF2-HMAC-SHA256 run. The iteration count is stored
+//! next to the hash, so it can change without breaking fobs already out
+//! there. An unpaired fob measures how many iterations it gets through in
+//! the time pairing allows at first boot, and hashes with that count once
+//! it is paired.
+//!
+//! The same run also gives the key FOB_SECRET_ENC is wrapped under (see
+//! wrap.rs), so the wrapped secret is no cheaper to guess a PIN against than
+//! the hash, and the paired fob has it as soon as the hash is done.
+
+use core::hint::black_box;
+
+use crate::{
+    aead::LEN_KEY,
+    clock::{Clock, TICKS_PER_US},
+    kdf::{hmac_sha256, Pbkdf2},
+};
+
+/// How long the PIN hash may take, which the fob works through while it
+/// waits up to 500ms for PAIR_ACK anyway
+pub const US_PIN_HASH:             u64 = 400_000;
+/// About `US_PIN_HASH` on the board at ~100us an iteration. Used where the
+/// count cannot be measured: by the provisioning script for paired fobs (it
+/// asks wrap_key for it) and by fobs whose timer did not move
+pub const PIN_HASH_ITERATIONS:     u32 = 4096;
+pub const MIN_PIN_HASH_ITERATIONS: u32 = 1024;
+pub const MAX_PIN_HASH_ITERATIONS: u32 = 1 << 16;
+/// Iterations `calibrate_iterations()` times, about 25ms on the board
+pub const CALIBRATION_ITERATIONS:  u32 = 256;
+/// Iterations to run on each pass through the main loop, a few ms worth
+pub const PIN_HASH_STEP:           u32 = 32;
+
+pub const LEN_PIN_HASH:            usize = 32;
+
+/// Keeps the PIN hash apart from anything else derived from the same PIN
+/// and salt.
+const PIN_HASH_LABEL: &[u8] = b"PwnyPARED PIN hash";
+/// Split the PBKDF2 output into the value stored in PIN_HASH and the
+/// wrapping key. Neither tells anything about the other.
+const PIN_CHECK_LABEL: &[u8] = b"PwnyPARED PIN check";
+const WRAP_KEY_LABEL: &[u8] = b"PwnyPARED wrap key";
+
+/// What hashing a PIN gives.
+pub struct PinKeys {
+    /// Stored in PIN_HASH and compared against on the next PAIR_REQ
+    pub check: [u8; LEN_PIN_HASH],
+    /// Wraps and unwraps FOB_SECRET_ENC
+    pub wrap_key: [u8; LEN_KEY],
+}
+
+impl PinKeys {
+    fn new(hash: &[u8]) -> PinKeys {
+        PinKeys {
+            check: hmac_sha256(hash, PIN_CHECK_LABEL),
+            wrap_key: hmac_sha256(hash, WRAP_KEY_LABEL),
+        }
+    }
+}
+
+/// A PIN hash being worked out, a few iterations at a time.
+#[derive(Clone)]
+pub struct PinHash(Pbkdf2);
+
+impl PinHash {
+    /// Starts hashing `pin`. The iteration count is capped at
+    /// `MAX_PIN_HASH_ITERATIONS`, so a damaged EEPROM cannot hang the fob.
+    pub fn new(pin: &[u8], salt: &[u8], iterations: u32) -> PinHash {
+        let iterations = iterations.clamp(1, MAX_PIN_HASH_ITERATIONS);
+        PinHash(Pbkdf2::labelled(pin, PIN_HASH_LABEL, salt, iterations))
+    }
+
+    /// Runs another `PIN_HASH_STEP` iterations. Returns true once done.
+    pub fn step(&mut self) -> bool {
+        self.0.step(PIN_HASH_STEP)
+    }
+
+    /// Runs whatever is left and returns what the hash gives.
+    pub fn finish(self) -> PinKeys {
+        PinKeys::new(&self.0.finish())
+    }
+}
+
+/// Hashes `pin` in one go.
+pub fn pin_hash(pin: &[u8], salt: &[u8], iterations: u32) -> PinKeys {
+    PinHash::new(pin, salt, iterations).finish()
+}
+
+/// Times `CALIBRATION_ITERATIONS` iterations of the PIN hash and returns how
+/// many fit in `US_PIN_HASH`, between `MIN_PIN_HASH_ITERATIONS` and
+/// `MAX_PIN_HASH_ITERATIONS`. A timer that did not move gives
+/// `PIN_HASH_ITERATIONS`.
+pub fn calibrate_iterations(clock: &mut impl Clock) -> u32 {
+    let start = clock.get_tick_timer();
+    black_box(pin_hash(&[0; 3], &[0; 12], CALIBRATION_ITERATIONS));
+    let ticks = clock.get_tick_timer().saturating_sub(start);
+    if ticks == 0 {
+        return PIN_HASH_ITERATIONS;
+    }
+    let iterations = US_PIN_HASH * TICKS_PER_US * CALIBRATION_ITERATIONS as u64 / ticks;
+    iterations.clamp(MIN_PIN_HASH_ITERATIONS as u64, MAX_PIN_HASH_ITERATIONS as u64) as u32
+}
@@ -0,0 +1,575 @@
+//! The PwnyPARED wire format (see docs/protocol.md). Every message is a magic
+//! byte followed by a fixed-length body. The car, the fob and the host side
+//! of the tests all build and parse messages here.
+
+use p256_cortex_m4::{PublicKey, SecretKey};
+
+use sha2::{Digest, Sha256};
+
+use crate::aead::{self, LEN_AEAD_NONCE, LEN_KEY, LEN_TAG};
+use crate::transport::Transport;
+
+/**
+ * Magic Bytes
+ */
+pub const MAGIC_PAIR_REQ:         u8 = 0x40;
+pub const MAGIC_PAIR_SYN:         u8 = 0x41;
+pub const MAGIC_PAIR_ACK:         u8 = 0x42;
+pub const MAGIC_PAIR_FIN:         u8 = 0x43;
+pub const MAGIC_PAIR_RST:         u8 = 0x44;
+pub const MAGIC_PAIR_PIN:         u8 = 0x45;
+
+pub const MAGIC_ENAB_FEAT:        u8 = 0x50;
+
+pub const MAGIC_UNLOCK_REQ:       u8 = 0x60;
+pub const MAGIC_UNLOCK_CHAL:      u8 = 0x61;
+pub const MAGIC_UNLOCK_RESP:      u8 = 0x62;
+pub const MAGIC_UNLOCK_GOOD:      u8 = 0x63;
+pub const MAGIC_UNLOCK_FEAT:      u8 = 0x64;
+pub const MAGIC_UNLOCK_PING:      u8 = 0x65;
+pub const MAGIC_UNLOCK_PONG:      u8 = 0x66;
+pub const MAGIC_UNLOCK_RST:       u8 = 0x69;
+
+pub const MAGIC_LINK_DATA:        u8 = 0x70;
+pub const MAGIC_LINK_ACK:         u8 = 0x71;
+
+pub const MAGIC_CALIBRATE:        u8 = 0x80;
+
+pub const MAGIC_HOST_SUCCESS:     u8 = 0xAA;
+pub const MAGIC_HOST_FAILURE:     u8 = 0xBB;
+pub const MAGIC_HOST_LOCKED:      u8 = 0xBC;
+pub const MAGIC_HOST_REPLAY:      u8 = 0xBD;
+pub const MAGIC_HOST_CALIBRATED:  u8 = 0xBE;
+
+/**
+ * Field lengths
+ */
+pub const LEN_PIN:                usize = 3;
+pub const LEN_NONCE:              usize = 16; // 128-bit nonce
+pub const LEN_PING:               usize = 4; // UNLOCK_PING fits in one link chunk
+pub const LEN_SIG:                usize = 64;
+pub const LEN_SECRET:             usize = 32;
+pub const LEN_PUBLIC:             usize = 64;
+pub const LEN_CAR_ID:             usize = 4; // 1 byte at heart
+pub const LEN_FEAT_NUM:           usize = 4; // value of 1, 2, or 3
+pub const NUM_FEATURES:           usize = 3;
+pub const LEN_CHUNK:              usize = 6; // a whole LINK_DATA frame fits in the 16 byte UART FIFO
+pub const LEN_MS_LEFT:            usize = 4;
+pub const LEN_CHAL_COUNTER:       usize = 4; // at the front of the car's nonce
+pub const LEN_TICKS:              usize = 4;
+pub const LEN_PAIR_SECRETS:       usize = LEN_SECRET + LEN_CAR_ID + NUM_FEATURES * LEN_SIG + LEN_PUBLIC + LEN_CHAL_COUNTER;
+
+/**
+ * Message body lengths (without the magic byte)
+ */
+pub const MSGLEN_PAIR_REQ:        usize = LEN_PIN;
+pub const MSGLEN_PAIR_SYN:        usize = LEN_PUBLIC;
+pub const MSGLEN_PAIR_ACK:        usize = LEN_PUBLIC;
+pub const MSGLEN_PAIR_PIN:        usize = LEN_PIN + LEN_TAG;
+pub const MSGLEN_PAIR_FIN:        usize = LEN_PAIR_SECRETS + LEN_TAG;
+pub const MSGLEN_ENAB_FEAT:       usize = LEN_CAR_ID + LEN_FEAT_NUM + LEN_SIG;
+pub const MSGLEN_UNLOCK_CHAL:     usize = LEN_NONCE + LEN_SIG;
+pub const MSGLEN_UNLOCK_RESP:     usize = LEN_NONCE + LEN_SIG;
+pub const MSGLEN_UNLOCK_FEAT:     usize = NUM_FEATURES * LEN_SIG;
+pub const MSGLEN_UNLOCK_PING:     usize = LEN_PING;
+pub const MSGLEN_UNLOCK_PONG:     usize = LEN_PING;
+pub const MSGLEN_LINK_DATA:       usize = 2 + LEN_CHUNK;
+pub const MSGLEN_LINK_ACK:        usize = 1;
+pub const MSGLEN_HOST_LOCKED:     usize = LEN_MS_LEFT;
+pub const MSGLEN_HOST_REPLAY:     usize = LEN_CHAL_COUNTER;
+pub const MSGLEN_HOST_CALIBRATED: usize = LEN_TICKS;
+
+/// Length of the longest message, magic byte included.
+pub const MAX_MSG_LEN:            usize = 1 + MSGLEN_PAIR_FIN;
+
+/// How long the rest of a message may take once its magic byte has arrived.
+pub const US_MSG_TIMEOUT:         u64 = 500_000;
+
+/// A PwnyPARED message.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Message {
+    /// Host to paired fob: start pairing with this PIN
+    PairReq { pin: [u8; LEN_PIN] },
+    /// Paired fob to unpaired fob: start pairing. Carries the paired fob's
+    /// half of the key exchange.
+    PairSyn { eph_public: [u8; LEN_PUBLIC] },
+    /// Unpaired fob to paired fob: ready to pair. Carries the unpaired fob's
+    /// half of the key exchange.
+    PairAck { eph_public: [u8; LEN_PUBLIC] },
+    /// Paired fob to unpaired fob: the PIN from PAIR_REQ, encrypted with the
+    /// key from the exchange
+    PairPin { sealed: [u8; LEN_PIN], tag: [u8; LEN_TAG] },
+    /// Paired fob to unpaired fob: `PairSecrets`, encrypted with the key
+    /// from the exchange
+    PairFin { sealed: [u8; LEN_PAIR_SECRETS], tag: [u8; LEN_TAG] },
+    /// Paired fob to unpaired fob: pairing failed
+    PairRst,
+    /// Host to paired fob: store a feature package from package_tool
+    EnabFeat {
+        car_id: [u8; LEN_CAR_ID],
+        feature_num: [u8; LEN_FEAT_NUM],
+        feature_sig: [u8; LEN_SIG],
+    },
+    /// Fob to car: SW1 was pressed
+    UnlockReq,
+    /// Car to fob: answer right away, the car is timing it
+    UnlockPing { ping: [u8; LEN_PING] },
+    /// Fob to car: random bytes, sent as soon as UNLOCK_PING arrives
+    UnlockPong { pong: [u8; LEN_PING] },
+    /// Car to fob: the car's nonce, with the car's signature of
+    /// `chal_transcript()`
+    UnlockChal { nonce: [u8; LEN_NONCE], nonce_sig: [u8; LEN_SIG] },
+    /// Fob to car: the fob's nonce, with the fob's signature of
+    /// `resp_transcript()`
+    UnlockResp { nonce: [u8; LEN_NONCE], nonce_sig: [u8; LEN_SIG] },
+    /// Car to fob: unlocked, send features
+    UnlockGood,
+    /// Fob to car: the stored feature signatures, in order
+    UnlockFeat { feature_sigs: [[u8; LEN_SIG]; NUM_FEATURES] },
+    /// Car or fob: unlocking failed
+    UnlockRst,
+    /// Board link: the next chunk of a message (see link.rs). `restart` marks
+    /// the first chunk after the sender dropped what it was sending.
+    LinkData { seq: u8, restart: bool, len: u8, data: [u8; LEN_CHUNK] },
+    /// Board link: the chunk with this sequence number arrived
+    LinkAck { seq: u8 },
+    /// Host to car: set the round trip limit from the next good unlock
+    Calibrate,
+    /// Fob to host: the command worked
+    HostSuccess,
+    /// Fob to host: the command failed
+    HostFailure,
+    /// Paired fob to host: too many wrong PINs, try again in this many
+    /// milliseconds (big endian)
+    HostLocked { ms_left: [u8; LEN_MS_LEFT] },
+    /// Paired fob to host: refused a car challenge older than one it has
+    /// already answered, which had this counter (big endian)
+    HostReplay { chal_counter: [u8; LEN_CHAL_COUNTER] },
+    /// Car to host: calibrated, UNLOCK_PONG must now arrive within this many
+    /// ticks of UNLOCK_PING (big endian)
+    HostCalibrated { rtt_limit: [u8; LEN_TICKS] },
+}
+
+/// Why bytes could not be decoded into a message.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DecodeError {
+    /// There were no bytes at all
+    Empty,
+    /// The first byte is not a known magic byte
+    BadMagic(u8),
+    /// The message stopped before the end of its body
+    Short { expected: usize, actual: usize },
+    /// There were bytes after the end of the message
+    Long { expected: usize, actual: usize },
+}
+
+/// Why a message could not be encoded.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EncodeError {
+    /// The buffer cannot fit the whole message
+    BufferTooSmall { needed: usize },
+}
+
+impl Message {
+    /// The magic byte that starts this message.
+    pub fn magic(&self) -> u8 {
+        match self {
+            Message::PairReq { .. } => MAGIC_PAIR_REQ,
+            Message::PairSyn { .. } => MAGIC_PAIR_SYN,
+            Message::PairAck { .. } => MAGIC_PAIR_ACK,
+            Message::PairPin { .. } => MAGIC_PAIR_PIN,
+            Message::PairFin { .. } => MAGIC_PAIR_FIN,
+            Message::PairRst => MAGIC_PAIR_RST,
+            Message::EnabFeat { .. } => MAGIC_ENAB_FEAT,
+            Message::UnlockReq => MAGIC_UNLOCK_REQ,
+            Message::UnlockPing { .. } => MAGIC_UNLOCK_PING,
+            Message::UnlockPong { .. } => MAGIC_UNLOCK_PONG,
+            Message::UnlockChal { .. } => MAGIC_UNLOCK_CHAL,
+            Message::UnlockResp { .. } => MAGIC_UNLOCK_RESP,
+            Message::UnlockGood => MAGIC_UNLOCK_GOOD,
+            Message::UnlockFeat { .. } => MAGIC_UNLOCK_FEAT,
+            Message::UnlockRst => MAGIC_UNLOCK_RST,
+            Message::LinkData { .. } => MAGIC_LINK_DATA,
+            Message::LinkAck { .. } => MAGIC_LINK_ACK,
+            Message::Calibrate => MAGIC_CALIBRATE,
+            Message::HostSuccess => MAGIC_HOST_SUCCESS,
+            Message::HostFailure => MAGIC_HOST_FAILURE,
+            Message::HostLocked { .. } => MAGIC_HOST_LOCKED,
+            Message::HostReplay { .. } => MAGIC_HOST_REPLAY,
+            Message::HostCalibrated { .. } => MAGIC_HOST_CALIBRATED,
+        }
+    }
+
+    /// Length of the body that follows a magic byte.
+    pub fn body_len(magic: u8) -> Result<usize, DecodeError> {
+        match magic {
+            MAGIC_PAIR_REQ => Ok(MSGLEN_PAIR_REQ),
+            MAGIC_PAIR_SYN => Ok(MSGLEN_PAIR_SYN),
+            MAGIC_PAIR_ACK => Ok(MSGLEN_PAIR_ACK),
+            MAGIC_PAIR_PIN => Ok(MSGLEN_PAIR_PIN),
+            MAGIC_PAIR_FIN => Ok(MSGLEN_PAIR_FIN),
+            MAGIC_ENAB_FEAT => Ok(MSGLEN_ENAB_FEAT),
+            MAGIC_UNLOCK_CHAL => Ok(MSGLEN_UNLOCK_CHAL),
+            MAGIC_UNLOCK_RESP => Ok(MSGLEN_UNLOCK_RESP),
+            MAGIC_UNLOCK_FEAT => Ok(MSGLEN_UNLOCK_FEAT),
+            MAGIC_UNLOCK_PING => Ok(MSGLEN_UNLOCK_PING),
+            MAGIC_UNLOCK_PONG => Ok(MSGLEN_UNLOCK_PONG),
+            MAGIC_LINK_DATA => Ok(MSGLEN_LINK_DATA),
+            MAGIC_LINK_ACK => Ok(MSGLEN_LINK_ACK),
+            MAGIC_HOST_LOCKED => Ok(MSGLEN_HOST_LOCKED),
+            MAGIC_HOST_REPLAY => Ok(MSGLEN_HOST_REPLAY),
+            MAGIC_HOST_CALIBRATED => Ok(MSGLEN_HOST_CALIBRATED),
+            MAGIC_PAIR_RST | MAGIC_UNLOCK_REQ | MAGIC_UNLOCK_GOOD | MAGIC_UNLOCK_RST
+            | MAGIC_CALIBRATE | MAGIC_HOST_SUCCESS | MAGIC_HOST_FAILURE => Ok(0),
+            _ => Err(DecodeError::BadMagic(magic)),
+        }
+    }
+
+    /// Length of the encoded message, magic byte included.
+    pub fn encoded_len(&self) -> usize {
+        1 + Message::body_len(self.magic()).unwrap()
+    }
+
+    /// Writes the message into the start of `buf`. Returns the number of
+    /// bytes written.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let len = self.encoded_len();
+        if buf.len() < len {
+            return Err(EncodeError::BufferTooSmall { needed: len });
+        }
+        buf[0] = self.magic();
+        let mut body = &mut buf[1..len];
+        match self {
+            Message::PairReq { pin } => {
+                put(&mut body, pin);
+            }
+            Message::PairSyn { eph_public } | Message::PairAck { eph_public } => {
+                put(&mut body, eph_public);
+            }
+            Message::PairPin { sealed, tag } => {
+                put(&mut body, sealed);
+                put(&mut body, tag);
+            }
+            Message::PairFin { sealed, tag } => {
+                put(&mut body, sealed);
+                put(&mut body, tag);
+            }
+            Message::EnabFeat { car_id, feature_num, feature_sig } => {
+                put(&mut body, car_id);
+                put(&mut body, feature_num);
+                put(&mut body, feature_sig);
+            }
+            Message::UnlockChal { nonce, nonce_sig } | Message::UnlockResp { nonce, nonce_sig } => {
+                put(&mut body, nonce);
+                put(&mut body, nonce_sig);
+            }
+            Message::UnlockPing { ping: bytes } | Message::UnlockPong { pong: bytes } => {
+                put(&mut body, bytes);
+            }
+            Message::UnlockFeat { feature_sigs } => {
+                for sig in feature_sigs {
+                    put(&mut body, sig);
+                }
+            }
+            Message::LinkData { seq, restart, len, data } => {
+                put(&mut body, &[*seq, (*restart as u8) << 7 | *len]);
+                put(&mut body, data);
+            }
+            Message::LinkAck { seq } => {
+                put(&mut body, &[*seq]);
+            }
+            Message::HostLocked { ms_left } => {
+                put(&mut body, ms_left);
+            }
+            Message::HostReplay { chal_counter } => {
+                put(&mut body, chal_counter);
+            }
+            Message::HostCalibrated { rtt_limit } => {
+                put(&mut body, rtt_limit);
+            }
+            _ => {}
+        }
+        Ok(len)
+    }
+
+    /// Parses exactly one message, magic byte included.
+    pub fn decode(data: &[u8]) -> Result<Message, DecodeError> {
+        let (&magic, mut body) = data.split_first().ok_or(DecodeError::Empty)?;
+        let expected = 1 + Message::body_len(magic)?;
+        if data.len() < expected {
+            return Err(DecodeError::Short { expected, actual: data.len() });
+        }
+        if data.len() > expected {
+            return Err(DecodeError::Long { expected, actual: data.len() });
+        }
+        let msg = match magic {
+            MAGIC_PAIR_REQ => Message::PairReq { pin: take(&mut body) },
+            MAGIC_PAIR_SYN => Message::PairSyn { eph_public: take(&mut body) },
+            MAGIC_PAIR_ACK => Message::PairAck { eph_public: take(&mut body) },
+            MAGIC_PAIR_PIN => Message::PairPin { sealed: take(&mut body), tag: take(&mut body) },
+            MAGIC_PAIR_FIN => Message::PairFin { sealed: take(&mut body), tag: take(&mut body) },
+            MAGIC_PAIR_RST => Message::PairRst,
+            MAGIC_ENAB_FEAT => Message::EnabFeat {
+                car_id: take(&mut body),
+                feature_num: take(&mut body),
+                feature_sig: take(&mut body),
+            },
+            MAGIC_UNLOCK_REQ => Message::UnlockReq,
+            MAGIC_UNLOCK_CHAL => Message::UnlockChal { nonce: take(&mut body), nonce_sig: take(&mut body) },
+            MAGIC_UNLOCK_RESP => Message::UnlockResp { nonce: take(&mut body), nonce_sig: take(&mut body) },
+            MAGIC_UNLOCK_PING => Message::UnlockPing { ping: take(&mut body) },
+            MAGIC_UNLOCK_PONG => Message::UnlockPong { pong: take(&mut body) },
+            MAGIC_UNLOCK_GOOD => Message::UnlockGood,
+            MAGIC_UNLOCK_FEAT => Message::UnlockFeat {
+                feature_sigs: [take(&mut body), take(&mut body), take(&mut body)],
+            },
+            MAGIC_UNLOCK_RST => Message::UnlockRst,
+            MAGIC_LINK_DATA => {
+                let [seq, info] = take(&mut body);
+                Message::LinkData { seq, restart: info & 0x80 != 0, len: info & 0x7F, data: take(&mut body) }
+            }
+            MAGIC_LINK_ACK => {
+                let [seq] = take(&mut body);
+                Message::LinkAck { seq }
+            }
+            MAGIC_CALIBRATE => Message::Calibrate,
+            MAGIC_HOST_SUCCESS => Message::HostSuccess,
+            MAGIC_HOST_FAILURE => Message::HostFailure,
+            MAGIC_HOST_LOCKED => Message::HostLocked { ms_left: take(&mut body) },
+            MAGIC_HOST_REPLAY => Message::HostReplay { chal_counter: take(&mut body) },
+            MAGIC_HOST_CALIBRATED => Message::HostCalibrated { rtt_limit: take(&mut body) },
+            _ => return Err(DecodeError::BadMagic(magic)),
+        };
+        Ok(msg)
+    }
+
+    /// Encodes the message and writes it out.
+    pub fn send(&self, mut transport: impl Transport) {
+        let mut buf: [u8; MAX_MSG_LEN] = [0; MAX_MSG_LEN];
+        let len = self.encode(&mut buf).unwrap();
+        transport.write(&buf[..len]);
+    }
+}
+
+/// Everything a paired fob stores, as sent in PAIR_FIN.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PairSecrets {
+    pub fob_secret: [u8; LEN_SECRET],
+    pub car_id: [u8; LEN_CAR_ID],
+    pub feature_sigs: [[u8; LEN_SIG]; NUM_FEATURES],
+    pub car_public: [u8; LEN_PUBLIC],
+    /// Lowest car challenge counter the new fob should answer (big endian)
+    pub next_chal: [u8; LEN_CHAL_COUNTER],
+}
+
+/// Domain separation for the PAIR_FIN key.
+const PAIR_KEY_LABEL: &[u8] = b"PwnyPARED PAIR_FIN key";
+
+/// Derives the PAIR_PIN and PAIR_FIN key on either fob, from its own ephemeral secret,
+/// the other fob's ephemeral public key, and both public keys as sent in
+/// PAIR_SYN and PAIR_ACK. Returns None if the other fob's public key is not
+/// a point on the curve.
+pub fn pair_key(
+    own_secret: &SecretKey,
+    peer_public: &[u8; LEN_PUBLIC],
+    syn_public: &[u8; LEN_PUBLIC],
+    ack_public: &[u8; LEN_PUBLIC],
+) -> Option<[u8; LEN_KEY]> {
+    let peer_public = PublicKey::from_untagged_bytes(peer_public).ok()?;
+    let shared = own_secret.agree(&peer_public);
+    let mut hash = Sha256::new();
+    hash.update(PAIR_KEY_LABEL);
+    hash.update(shared.as_bytes());
+    hash.update(syn_public);
+    hash.update(ack_public);
+    Some(hash.finalize().into())
+}
+
+// Every pairing key is fresh and seals one PAIR_PIN and one PAIR_FIN, so a
+// fixed nonce for each is safe
+const PAIR_PIN_NONCE: [u8; LEN_AEAD_NONCE] = [1; LEN_AEAD_NONCE];
+const PAIR_FIN_NONCE: [u8; LEN_AEAD_NONCE] = [0; LEN_AEAD_NONCE];
+
+/// Encrypts the PIN into a PAIR_PIN.
+pub fn seal_pin(pin: &[u8; LEN_PIN], key: &[u8; LEN_KEY]) -> Message {
+    let mut sealed = *pin;
+    let tag = aead::seal(key, &PAIR_PIN_NONCE, &[MAGIC_PAIR_PIN], &mut sealed);
+    Message::PairPin { sealed, tag }
+}
+
+/// Decrypts the PIN from a PAIR_PIN. Returns None if anything was changed on
+/// the way, or the PAIR_PIN was made with another key.
+pub fn open_pin(sealed: &[u8; LEN_PIN], tag: &[u8; LEN_TAG], key: &[u8; LEN_KEY]) -> Option<[u8; LEN_PIN]> {
+    let mut pin = *sealed;
+    aead::open(key, &PAIR_PIN_NONCE, &[MAGIC_PAIR_PIN], &mut pin, tag).then_some(pin)
+}
+
+impl PairSecrets {
+    /// Encrypts the secrets into a PAIR_FIN.
+    pub fn seal(&self, key: &[u8; LEN_KEY]) -> Message {
+        let mut sealed = [0u8; LEN_PAIR_SECRETS];
+        let mut body = &mut sealed[..];
+        put(&mut body, &self.fob_secret);
+        put(&mut body, &self.car_id);
+        for sig in &self.feature_sigs {
+            put(&mut body, sig);
+        }
+        put(&mut body, &self.car_public);
+        put(&mut body, &self.next_chal);
+        let tag = aead::seal(key, &PAIR_FIN_NONCE, &[MAGIC_PAIR_FIN], &mut sealed);
+        Message::PairFin { sealed, tag }
+    }
+
+    /// Decrypts the secrets from a PAIR_FIN. Returns None if anything was
+    /// changed on the way, or the PAIR_FIN was made with another key.
+    pub fn open(sealed: &[u8; LEN_PAIR_SECRETS], tag: &[u8; LEN_TAG], key: &[u8; LEN_KEY]) -> Option<PairSecrets> {
+        let mut data = *sealed;
+        if !aead::open(key, &PAIR_FIN_NONCE, &[MAGIC_PAIR_FIN], &mut data, tag) {
+            return None;
+        }
+        let mut body = &data[..];
+        Some(PairSecrets {
+            fob_secret: take(&mut body),
+            car_id: take(&mut body),
+            feature_sigs: [take(&mut body), take(&mut body), take(&mut body)],
+            car_public: take(&mut body),
+            next_chal: take(&mut body),
+        })
+    }
+}
+
+/// Domain separation for the unlock signatures.
+const UNLOCK_CHAL_LABEL: &[u8] = b"PwnyPARED UNLOCK_CHAL";
+const UNLOCK_RESP_LABEL: &[u8] = b"PwnyPARED UNLOCK_RESP";
+
+const LEN_PING_TRANSCRIPT:        usize = 1 + 1 + MSGLEN_UNLOCK_PING + 1 + MSGLEN_UNLOCK_PONG;
+pub const LEN_CHAL_TRANSCRIPT:    usize = UNLOCK_CHAL_LABEL.len() + LEN_CAR_ID + LEN_PING_TRANSCRIPT + 1 + LEN_NONCE;
+pub const LEN_RESP_TRANSCRIPT:    usize =
+    UNLOCK_RESP_LABEL.len() + LEN_CAR_ID + LEN_PING_TRANSCRIPT + 1 + MSGLEN_UNLOCK_CHAL + 1 + LEN_NONCE;
+
+/// What the car signs in UNLOCK_CHAL: its label, the car ID, UNLOCK_REQ,
+/// UNLOCK_PING, UNLOCK_PONG and UNLOCK_CHAL up to the signature.
+pub fn chal_transcript(
+    car_id: &[u8; LEN_CAR_ID],
+    ping: &[u8; LEN_PING],
+    pong: &[u8; LEN_PING],
+    car_nonce: &[u8; LEN_NONCE],
+) -> [u8; LEN_CHAL_TRANSCRIPT] {
+    transcript(&[
+        UNLOCK_CHAL_LABEL, car_id,
+        &[MAGIC_UNLOCK_REQ],
+        &[MAGIC_UNLOCK_PING], ping,
+        &[MAGIC_UNLOCK_PONG], pong,
+        &[MAGIC_UNLOCK_CHAL], car_nonce,
+    ])
+}
+
+/// What the fob signs in UNLOCK_RESP: its label, the car ID, UNLOCK_REQ,
+/// UNLOCK_PING, UNLOCK_PONG, the whole UNLOCK_CHAL and UNLOCK_RESP up to the
+/// signature. Ties the answer to this car and this challenge.
+pub fn resp_transcript(
+    car_id: &[u8; LEN_CAR_ID],
+    ping: &[u8; LEN_PING],
+    pong: &[u8; LEN_PING],
+    car_nonce: &[u8; LEN_NONCE],
+    car_nonce_sig: &[u8; LEN_SIG],
+    fob_nonce: &[u8; LEN_NONCE],
+) -> [u8; LEN_RESP_TRANSCRIPT] {
+    transcript(&[
+        UNLOCK_RESP_LABEL, car_id,
+        &[MAGIC_UNLOCK_REQ],
+        &[MAGIC_UNLOCK_PING], ping,
+        &[MAGIC_UNLOCK_PONG], pong,
+        &[MAGIC_UNLOCK_CHAL], car_nonce, car_nonce_sig,
+        &[MAGIC_UNLOCK_RESP], fob_nonce,
+    ])
+}
+
+/// Puts `parts` one after another. They must add up to exactly `N` bytes.
+fn transcript<const N: usize>(parts: &[&[u8]]) -> [u8; N] {
+    let mut out = [0u8; N];
+    let mut body = &mut out[..];
+    for part in parts {
+        put(&mut body, part);
+    }
+    debug_assert!(body.is_empty());
+    out
+}
+
+/// Copies a field to the front of `body` and moves past it.
+fn put(body: &mut &mut [u8], field: &[u8]) {
+    let (head, rest) = core::mem::take(body).split_at_mut(field.len());
+    head.copy_from_slice(field);
+    *body = rest;
+}
+
+/// Takes a field off the front of `body`. Lengths are checked by `decode()`.
+fn take<const N: usize>(body: &mut &[u8]) -> [u8; N] {
+    let (head, rest) = body.split_at(N);
+    *body = rest;
+    head.try_into().unwrap()
+}
+
+/// Puts messages back together as their bytes arrive one at a time.
+pub struct MsgReader {
+    buf: [u8; MAX_MSG_LEN],
+    len: usize,
+    want: usize,
+    started_us: u64,
+}
+
+impl MsgReader {
+    pub const fn new() -> MsgReader {
+        MsgReader { buf: [0; MAX_MSG_LEN], len: 0, want: 0, started_us: 0 }
+    }
+
+    /// Adds a byte that arrived at `now_us`, like `push()`, and remembers
+    /// when the message started so `expire()` can drop it later.
+    pub fn push_at(&mut self, byte: u8, now_us: u64) -> Option<Result<Message, DecodeError>> {
+        if self.len == 0 {
+            self.started_us = now_us;
+        }
+        self.push(byte)
+    }
+
+    /// Drops a partly received message that started more than
+    /// `US_MSG_TIMEOUT` ago, so a sender that stopped halfway does not
+    /// swallow the start of the next message. Returns true if one was dropped.
+    pub fn expire(&mut self, now_us: u64) -> bool {
+        if self.len > 0 && now_us >= self.started_us + US_MSG_TIMEOUT {
+            self.len = 0;
+            return true;
+        }
+        false
+    }
+
+    /// Adds a byte. Returns the message once its last byte has arrived, or an
+    /// error if a message cannot start with this byte.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Message, DecodeError>> {
+        if self.len == 0 {
+            match Message::body_len(byte) {
+                Ok(body_len) => self.want = 1 + body_len,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < self.want {
+            return None;
+        }
+        let len = self.len;
+        self.len = 0;
+        Some(Message::decode(&self.buf[..len]))
+    }
+
+    /// Drops a partly received message.
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for MsgReader {
+    fn default() -> MsgReader {
+        MsgReader::new()
+    }
+}
@@ -0,0 +1,123 @@
+//! Deterministic ECDSA nonces for P-256 with SHA-256 (RFC 6979). The nonce
+//! comes from HMAC-SHA256 over the secret key and the message hash, so a
+//! weak or repeating RNG can never make two signatures share a nonce and
+//! give away the key.
+//!
+//! Extra data can be mixed in as RFC 6979 section 3.6 allows. With fresh
+//! random bytes this hedges the signature: it stays safe if the RNG is
+//! broken, and a fault injected into one signature cannot be lined up with
+//! another of the same message.
+
+use rand_chacha::rand_core::{impls, CryptoRng, Error, RngCore};
+
+use crate::kdf::{HmacSha256, LEN_MAC};
+
+pub const LEN_SCALAR:             usize = 32;
+pub const LEN_HEDGE:              usize = 32; // random bytes mixed into every nonce
+
+/// The order of the P-256 group, big endian.
+const ORDER: [u8; LEN_SCALAR] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xBC, 0xE6, 0xFA, 0xAD, 0xA7, 0x17, 0x9E, 0x84, 0xF3, 0xB9, 0xCA, 0xC2, 0xFC, 0x63, 0x25, 0x51,
+];
+
+/// The HMAC_DRBG of RFC 6979 section 3.2, handing out candidate nonces.
+pub struct Rfc6979 {
+    k: [u8; LEN_MAC],
+    v: [u8; LEN_MAC],
+    started: bool,
+}
+
+impl Rfc6979 {
+    /// Steps a. to g. for the secret key `x` and the message hash `h1`, both
+    /// big endian. `extra` may be empty, which gives the plain RFC nonces.
+    pub fn new(x: &[u8; LEN_SCALAR], h1: &[u8; LEN_MAC], extra: &[u8]) -> Rfc6979 {
+        let h1 = reduce(h1);
+        let mut drbg = Rfc6979 { k: [0; LEN_MAC], v: [1; LEN_MAC], started: false };
+        drbg.rekey(0x00, &[x, &h1, extra]);
+        drbg.rekey(0x01, &[x, &h1, extra]);
+        drbg
+    }
+
+    /// The next candidate nonce, big endian (step h.). The first is the one
+    /// the RFC signs with; later ones are for when a candidate is out of
+    /// range or gives r = 0 or s = 0.
+    pub fn next_k(&mut self) -> [u8; LEN_SCALAR] {
+        if self.started {
+            self.rekey(0x00, &[]);
+        }
+        self.started = true;
+        // qlen is 256 bits, so one block is a whole candidate
+        self.v = self.mac(&[&self.v]);
+        self.v
+    }
+
+    /// K = HMAC_K(V || sep || data), V = HMAC_K(V)
+    fn rekey(&mut self, sep: u8, data: &[&[u8]]) {
+        let mut mac = HmacSha256::new(&self.k);
+        mac.update(&self.v);
+        mac.update(&[sep]);
+        for part in data {
+            mac.update(part);
+        }
+        self.k = mac.finish();
+        self.v = self.mac(&[&self.v]);
+    }
+
+    fn mac(&self, data: &[&[u8]]) -> [u8; LEN_MAC] {
+        let mut mac = HmacSha256::new(&self.k);
+        for part in data {
+            mac.update(part);
+        }
+        mac.finish()
+    }
+}
+
+/// Hands the candidates to `SecretKey::sign_prehashed()` as if they were
+/// random, one per 32 bytes asked for. The signer already draws again when a
+/// candidate does not work, which is what the RFC asks for.
+impl RngCore for Rfc6979 {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(LEN_SCALAR) {
+            let k = self.next_k();
+            // The Cortex-M4 code reads its nonce as little endian words, the
+            // fallback reads big endian bytes
+            #[cfg(target_arch = "arm")]
+            let k = {
+                let mut k = k;
+                k.reverse();
+                k
+            };
+            chunk.copy_from_slice(&k[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for Rfc6979 {}
+
+/// bits2octets: the hash taken mod the group order. The hash is less than
+/// twice the order, so one subtraction does it.
+fn reduce(h: &[u8; LEN_MAC]) -> [u8; LEN_SCALAR] {
+    let mut diff = [0u8; LEN_SCALAR];
+    let mut borrow = 0i16;
+    for i in (0..LEN_SCALAR).rev() {
+        let d = h[i] as i16 - ORDER[i] as i16 - borrow;
+        diff[i] = d as u8;
+        borrow = (d < 0) as i16;
+    }
+    // A borrow out of the top means h < n, so keep h
+    if borrow == 1 { *h } else { diff }
+}
@@ -0,0 +1,194 @@
+//! HMAC_DRBG with SHA-256 (NIST SP 800-90A section 10.1.2), the random
+//! number generator behind nonces, pings and keys on both boards.
+//!
+//! It is seeded from an `EntropySource` and pulls fresh entropy from it
+//! again every `RESEED_INTERVAL` requests. The times of things the user does,
+//! like pressing SW1 or sending bytes over a UART, collect in an
+//! `EntropyPool`, which is emptied into every reseed. Once the pool holds
+//! `POOL_RESEED_BITS` the DRBG reseeds early. Mixing events in a few at a
+//! time would not help: anyone who knew the state could guess each batch.
+
+use rand_chacha::rand_core::{impls, CryptoRng, Error, RngCore};
+
+use crate::{
+    kdf::{HmacSha256, LEN_MAC},
+    EntropyPool, Event,
+};
+
+pub const LEN_ENTROPY:            usize = 32;
+pub const LEN_DRBG_NONCE:         usize = 16;
+pub const RESEED_INTERVAL:        u32 = 1024; // requests between pulls from the entropy source
+pub const LEN_RNG_SEED:           usize = 32; // seed kept in EEPROM from one boot to the next
+pub const POOL_RESEED_BITS:       u32 = 128; // reseed early once the event pool holds this much
+const MAX_REQUEST:                usize = 1 << 16; // SP 800-90A allows 2^19 bits per request
+
+/// Where the DRBG gets its entropy. On the board this is
+/// `get_combined_entropy()`; any function taking the pool and returning 32
+/// bytes will do.
+pub trait EntropySource {
+    /// Returns 32 bytes holding at least 128 bits of entropy. Events that
+    /// happen while gathering it can go into `pool`.
+    fn entropy(&mut self, pool: &mut EntropyPool) -> [u8; LEN_ENTROPY];
+}
+
+impl<F: FnMut(&mut EntropyPool) -> [u8; LEN_ENTROPY]> EntropySource for F {
+    fn entropy(&mut self, pool: &mut EntropyPool) -> [u8; LEN_ENTROPY] {
+        self(pool)
+    }
+}
+
+/// A random number generator that can take in events as they happen. The
+/// car and fob report button presses and received bytes through this.
+pub trait EventRng: CryptoRng + RngCore {
+    /// Adds an event that happened at `ticks` to the entropy pool.
+    fn add_event(&mut self, event: Event, ticks: u64);
+
+    /// Mixes `seed` into the state before the next request.
+    fn add_seed(&mut self, seed: &[u8]);
+}
+
+/// Mixes the seed saved on the last boot into `rng` and returns the one to
+/// save in its place. The new seed is drawn after the old one is mixed in, so
+/// it depends on every boot so far, and no seed is ever used twice as long as
+/// it is saved before `rng` hands out anything else.
+pub fn next_rng_seed<R: EventRng>(rng: &mut R, saved: &[u8; LEN_RNG_SEED]) -> [u8; LEN_RNG_SEED] {
+    rng.add_seed(saved);
+    let mut seed = [0u8; LEN_RNG_SEED];
+    rng.fill_bytes(&mut seed);
+    seed
+}
+
+/// HMAC_DRBG state, along with the source and event pool it reseeds from.
+pub struct HmacDrbg<E> {
+    k: [u8; LEN_MAC],
+    v: [u8; LEN_MAC],
+    reseed_counter: u32,
+    source: E,
+    pool: EntropyPool,
+}
+
+impl<E: EntropySource> HmacDrbg<E> {
+    /// Seeds the DRBG from `source`. The personalization string keeps
+    /// generators seeded from the same entropy apart, e.g. the car's from
+    /// the fob's.
+    pub fn new(mut source: E, personalization: &[u8]) -> Self {
+        let mut pool = EntropyPool::new();
+        let entropy = source.entropy(&mut pool);
+        let nonce = source.entropy(&mut pool);
+        let mut drbg = Self::instantiate(source, &entropy, &nonce[..LEN_DRBG_NONCE], personalization);
+        // Whatever the source added waits for the first reseed
+        drbg.pool = pool;
+        drbg
+    }
+
+    /// The instantiate function, with the entropy and nonce given.
+    pub fn instantiate(source: E, entropy: &[u8], nonce: &[u8], personalization: &[u8]) -> Self {
+        let mut drbg = HmacDrbg {
+            k: [0x00; LEN_MAC],
+            v: [0x01; LEN_MAC],
+            reseed_counter: 1,
+            source,
+            pool: EntropyPool::new(),
+        };
+        drbg.update(&[entropy, nonce, personalization]);
+        drbg
+    }
+
+    /// The reseed function, with fresh entropy from the source and everything
+    /// in the event pool.
+    pub fn reseed(&mut self, additional: &[u8]) {
+        let entropy = self.source.entropy(&mut self.pool);
+        let pooled = self.pool.take();
+        self.update(&[&entropy, &pooled, additional]);
+        self.reseed_counter = 1;
+    }
+
+    /// Estimated entropy waiting in the event pool, in bits.
+    pub fn pool_bits(&self) -> u32 {
+        self.pool.entropy_bits()
+    }
+
+    /// The generate function. Reseeds first if the last reseed was
+    /// `RESEED_INTERVAL` requests ago or the event pool is full enough.
+    /// `out` is at most 64 KiB.
+    pub fn generate(&mut self, out: &mut [u8], additional: &[u8]) {
+        assert!(out.len() <= MAX_REQUEST);
+        let mut additional = additional;
+        if self.reseed_counter > RESEED_INTERVAL || self.pool.entropy_bits() >= POOL_RESEED_BITS {
+            self.reseed(additional);
+            additional = &[];
+        }
+        if !additional.is_empty() {
+            self.update(&[additional]);
+        }
+        for chunk in out.chunks_mut(LEN_MAC) {
+            self.v = self.mac(&[&self.v]);
+            chunk.copy_from_slice(&self.v[..chunk.len()]);
+        }
+        self.update(&[additional]);
+        self.reseed_counter += 1;
+    }
+
+    /// K = HMAC_K(V || 0x00 || data), V = HMAC_K(V), and again with 0x01 if
+    /// there is any data.
+    fn update(&mut self, data: &[&[u8]]) {
+        for sep in [0x00, 0x01] {
+            let mut mac = HmacSha256::new(&self.k);
+            mac.update(&self.v);
+            mac.update(&[sep]);
+            for part in data {
+                mac.update(part);
+            }
+            self.k = mac.finish();
+            self.v = self.mac(&[&self.v]);
+            if data.iter().all(|part| part.is_empty()) {
+                return;
+            }
+        }
+    }
+
+    fn mac(&self, data: &[&[u8]]) -> [u8; LEN_MAC] {
+        let mut mac = HmacSha256::new(&self.k);
+        for part in data {
+            mac.update(part);
+        }
+        mac.finish()
+    }
+}
+
+impl<E: EntropySource> RngCore for HmacDrbg<E> {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    /// One request per 64 KiB.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(MAX_REQUEST) {
+            self.generate(chunk, &[]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl<E: EntropySource> CryptoRng for HmacDrbg<E> {}
+
+impl<E: EntropySource> EventRng for HmacDrbg<E> {
+    /// Only a hash for every byte off a UART. The HMACs wait until the pool
+    /// is emptied.
+    fn add_event(&mut self, event: Event, ticks: u64) {
+        self.pool.add(event, ticks);
+    }
+
+    /// Goes in right away, as if with fresh entropy.
+    fn add_seed(&mut self, seed: &[u8]) {
+        self.update(&[seed]);
+    }
+}
@@ -0,0 +1,24 @@
+/// Size of the TM4C123GH6PM EEPROM, and of the EEPROM images built by
+/// build_car_eeprom.py and build_fob_eeprom.py.
+pub const EEPROM_SIZE: u32 = 0x800; // 2K
+
+/// Persistent storage laid out like the EEPROM (see docs/state.md). On the
+/// board this is the EEPROM itself (see driverlib.rs). Off the board it can
+/// be an EEPROM image file (see host.rs).
+pub trait Storage {
+    /// Read from storage. Address must be a multiple of 4.
+    fn read(&mut self, data: &mut [u32], address: u32);
+
+    /// Write to storage. Address must be a multiple of 4.
+    fn write(&mut self, data: &[u32], address: u32);
+}
+
+impl<T: Storage> Storage for &mut T {
+    fn read(&mut self, data: &mut [u32], address: u32) {
+        (**self).read(data, address)
+    }
+
+    fn write(&mut self, data: &[u32], address: u32) {
+        (**self).write(data, address)
+    }
+}
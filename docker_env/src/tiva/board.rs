@@ -1,14 +1,4 @@
-#[derive(PartialEq, Clone, Copy)]
-/// The Launchpad has a tri-colour LED, which we consider
-/// to be three separate LEDs.
-pub enum Led {
-    /// The Red LED
-    Red,
-    /// The Blue LED
-    Blue,
-    /// The Green LED
-    Green,
-}
+pub use crate::led::Led;
 
 #[derive(PartialEq, Clone, Copy)]
 /// The Launchpad has two buttons
@@ -33,6 +23,7 @@ pub enum Button {
 //
 // ****************************************************************************
 
+use crate::led::Leds;
 use embedded_hal::digital::v2::OutputPin;
 use tm4c123x_hal::gpio::{gpiof::*, GpioExt, Input, Output, PullUp, PushPull};
 use tm4c123x_hal::sysctl::{
@@ -274,6 +265,19 @@ impl Board {
     }
 }
 
+impl Leds for Board {
+    fn set_led(&mut self, led: Led, on: bool) {
+        let _ = match (led, on) {
+            (Led::Red, true) => self.led_red.set_high(),
+            (Led::Red, false) => self.led_red.set_low(),
+            (Led::Blue, true) => self.led_blue.set_high(),
+            (Led::Blue, false) => self.led_blue.set_low(),
+            (Led::Green, true) => self.led_green.set_high(),
+            (Led::Green, false) => self.led_green.set_low(),
+        };
+    }
+}
+
 // ****************************************************************************
 //
 // Public Functions
@@ -0,0 +1,67 @@
+/// A byte stream to another party. On the board this is either the host UART
+/// or the board link UART (see driverlib.rs). Off the board it can be any
+/// other byte stream, such as the in-memory channels in host.rs.
+pub trait Transport {
+    /// Check if the other side has sent a byte.
+    fn avail(&mut self) -> bool;
+
+    /// Read a byte. Blocks until a byte is available.
+    fn readb(&mut self) -> u8;
+
+    /// Write a byte.
+    fn writeb(&mut self, data: u8);
+
+    /// Read bytes into an array. Only reads data.len() bytes.
+    fn read(&mut self, data: &mut [u8]) {
+        for byte in data {
+            *byte = self.readb();
+        }
+    }
+
+    /// Write bytes.
+    fn write(&mut self, data: &[u8]) {
+        for byte in data {
+            self.writeb(*byte);
+        }
+    }
+
+    /// Read bytes into an array, giving up as soon as `expired` returns true.
+    /// Returns true if all data.len() bytes were read before the deadline.
+    fn read_until(&mut self, data: &mut [u8], mut expired: impl FnMut() -> bool) -> bool {
+        for byte in data {
+            loop {
+                if self.avail() {
+                    *byte = self.readb();
+                    break;
+                }
+                if expired() {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// The two UARTs a board talks over.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Port {
+    /// UART to the host computer
+    Host,
+    /// UART to the other board
+    BoardLink,
+}
+
+impl<T: Transport> Transport for &mut T {
+    fn avail(&mut self) -> bool {
+        (**self).avail()
+    }
+
+    fn readb(&mut self) -> u8 {
+        (**self).readb()
+    }
+
+    fn writeb(&mut self, data: u8) {
+        (**self).writeb(data)
+    }
+}
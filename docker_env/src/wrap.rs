@@ -0,0 +1,59 @@
+//! Versioned records that keep a secret under a key from the PIN hash (see
+//! pin.rs). ChaCha20-Poly1305 encrypts the secret with it, so a wrong PIN or
+//! a changed record fails to unwrap instead of giving back some other
+//! secret.
+//!
+//! A record is 68 bytes, which fills whole EEPROM words:
+//!
+//!   version | reserved (7 bytes) | nonce | secret | tag
+//!
+//! The first 8 bytes are authenticated along with the secret.
+
+use crate::aead::{self, LEN_AEAD_NONCE, LEN_KEY, LEN_TAG};
+
+pub const WRAP_VERSION:           u8 = 1;
+
+pub const LEN_WRAPPED_SECRET:     usize = 32;
+const LEN_WRAP_HEADER:            usize = 8;
+pub const LEN_WRAPPED_KEY:        usize = LEN_WRAP_HEADER + LEN_AEAD_NONCE + LEN_WRAPPED_SECRET + LEN_TAG;
+
+/// Why a record did not unwrap.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum UnwrapError {
+    /// The record is from a format this firmware does not know
+    BadVersion(u8),
+    /// The PIN or salt is wrong, or the record was changed
+    BadTag,
+}
+
+/// Wraps `secret` under `key`. The nonce must be fresh for every record made
+/// with the same key.
+pub fn wrap(secret: &[u8; LEN_WRAPPED_SECRET], key: &[u8; LEN_KEY], nonce: &[u8; LEN_AEAD_NONCE]) -> [u8; LEN_WRAPPED_KEY] {
+    let mut record = [0u8; LEN_WRAPPED_KEY];
+    record[0] = WRAP_VERSION;
+    let (header, body) = record.split_at_mut(LEN_WRAP_HEADER);
+    let (nonce_out, body) = body.split_at_mut(LEN_AEAD_NONCE);
+    let (data, tag_out) = body.split_at_mut(LEN_WRAPPED_SECRET);
+    nonce_out.copy_from_slice(nonce);
+    data.copy_from_slice(secret);
+
+    let tag = aead::seal(key, nonce, header, data);
+    tag_out.copy_from_slice(&tag);
+    record
+}
+
+/// Gets the secret back out of a record made by `wrap()`.
+pub fn unwrap(record: &[u8; LEN_WRAPPED_KEY], key: &[u8; LEN_KEY]) -> Result<[u8; LEN_WRAPPED_SECRET], UnwrapError> {
+    if record[0] != WRAP_VERSION {
+        return Err(UnwrapError::BadVersion(record[0]));
+    }
+
+    let (header, body) = record.split_at(LEN_WRAP_HEADER);
+    let (nonce, body) = body.split_at(LEN_AEAD_NONCE);
+    let (data, tag) = body.split_at(LEN_WRAPPED_SECRET);
+    let mut secret: [u8; LEN_WRAPPED_SECRET] = data.try_into().unwrap();
+    if !aead::open(key, nonce.try_into().unwrap(), header, &mut secret, tag.try_into().unwrap()) {
+        return Err(UnwrapError::BadTag);
+    }
+    Ok(secret)
+}
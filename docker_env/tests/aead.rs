@@ -0,0 +1,88 @@
+#![cfg(feature = "std")]
+
+use tiva::aead::{chacha20_block, chacha20_xor, open, seal, Poly1305};
+use tiva::ct_eq;
+
+fn hex(s: &str) -> Vec<u8> {
+    let s: String = s.split_whitespace().collect();
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+fn key() -> [u8; 32] {
+    core::array::from_fn(|i| i as u8)
+}
+
+const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+// RFC 8439 2.3.2
+#[test]
+fn chacha20_block_matches_rfc() {
+    let nonce: [u8; 12] = hex("000000090000004a00000000").try_into().unwrap();
+    let block = chacha20_block(&key(), 1, &nonce);
+    assert_eq!(block[..16], hex("10f1e7e4d13b5915500fdd1fa32071c4"));
+    assert_eq!(block[48..], hex("b5129cd1de164eb9cbd083e8a2503c4e"));
+}
+
+// RFC 8439 2.4.2
+#[test]
+fn chacha20_encryption_matches_rfc() {
+    let nonce: [u8; 12] = hex("000000000000004a00000000").try_into().unwrap();
+    let mut data = SUNSCREEN.to_vec();
+    chacha20_xor(&key(), 1, &nonce, &mut data);
+    assert_eq!(data[..16], hex("6e2e359a2568f98041ba0728dd0d6981"));
+    assert_eq!(data[data.len() - 18..], hex("5af90bbf74a35be6b40b8eedf2785e42874d"));
+}
+
+// RFC 8439 2.5.2
+#[test]
+fn poly1305_matches_rfc() {
+    let key: [u8; 32] = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b").try_into().unwrap();
+    let mut mac = Poly1305::new(&key);
+    mac.update(b"Cryptographic ");
+    mac.update(b"Forum Research Group");
+    assert_eq!(mac.finish().to_vec(), hex("a8061dc1305136c6c22b8baf0c0127a9"));
+}
+
+// RFC 8439 2.8.2
+#[test]
+fn aead_matches_rfc() {
+    let key: [u8; 32] = hex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f").try_into().unwrap();
+    let nonce: [u8; 12] = hex("070000004041424344454647").try_into().unwrap();
+    let aad = hex("50515253c0c1c2c3c4c5c6c7");
+    let mut data = SUNSCREEN.to_vec();
+    let tag = seal(&key, &nonce, &aad, &mut data);
+    assert_eq!(data[..16], hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+    assert_eq!(tag.to_vec(), hex("1ae10b594f09e26a7e902ecbd0600691"));
+
+    assert!(open(&key, &nonce, &aad, &mut data, &tag));
+    assert_eq!(data, SUNSCREEN);
+}
+
+#[test]
+fn open_rejects_any_change() {
+    let nonce = [0; 12];
+    let mut data = SUNSCREEN.to_vec();
+    let tag = seal(&key(), &nonce, b"aad", &mut data);
+    let sealed = data.clone();
+
+    data[40] ^= 0x01;
+    assert!(!open(&key(), &nonce, b"aad", &mut data, &tag));
+    // Left as it was
+    assert_eq!(data[41..], sealed[41..]);
+
+    let mut data = sealed.clone();
+    assert!(!open(&key(), &nonce, b"aaD", &mut data, &tag));
+    let mut bad_tag = tag;
+    bad_tag[15] ^= 0x80;
+    assert!(!open(&key(), &nonce, b"aad", &mut data, &bad_tag));
+    assert!(!open(&key(), &[1; 12], b"aad", &mut data, &tag));
+    assert!(open(&key(), &nonce, b"aad", &mut data, &tag));
+}
+
+#[test]
+fn ct_eq_compares_whole_strings() {
+    assert!(ct_eq(b"abc", b"abc"));
+    assert!(!ct_eq(b"abc", b"abd"));
+    assert!(!ct_eq(b"abc", b"ab"));
+    assert!(ct_eq(b"", b""));
+}
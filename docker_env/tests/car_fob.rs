@@ -0,0 +1,975 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use p256_cortex_m4::SecretKey;
+use rand_chacha::{rand_core::{RngCore, SeedableRng}, ChaChaRng};
+use tiva::{
+    car::{Car, CarState, CARMEM_CHAL_COUNTER, CARMEM_RTT_LIMIT, CARMEM_UNLOCK_FAILS},
+    fob::{Fob, FobState, FOBMEM_NEXT_CHAL, FOBMEM_PIN_FAILS},
+    host::{channel_pair, Channel, MemStorage, VirtualClock},
+    frame::MAX_FRAME_LEN,
+    link::Link,
+    protocol::{pair_key, resp_transcript, seal_pin, Message, MsgReader, PairSecrets, LEN_CHUNK, LEN_NONCE, LEN_PING},
+    pin::{pin_hash, PIN_HASH_ITERATIONS, PIN_HASH_STEP},
+    rng::LEN_RNG_SEED,
+    wrap::{unwrap, wrap},
+    Port, Signer, Transport,
+};
+
+use common::*;
+
+type TestCar = Car<Channel, Channel, MemStorage, VirtualClock, NoLeds, TestRng>;
+type TestFob = Fob<Channel, Channel, MemStorage, VirtualClock, NoLeds, TestRng>;
+
+/// A car and fob wired together, sharing one clock.
+struct Bench {
+    car: TestCar,
+    fob: TestFob,
+    car_host: Channel,
+    fob_host: Channel,
+    clock: VirtualClock,
+}
+
+impl Bench {
+    fn new(car_storage: MemStorage, fob_storage: MemStorage) -> Bench {
+        let clock = VirtualClock::new();
+        let (car_link, fob_link) = channel_pair();
+        let (car_host, car_host_end) = channel_pair();
+        let (fob_host, fob_host_end) = channel_pair();
+        let rng = TestRng::from_seed([2; 32]);
+        Bench {
+            car: Car::new(car_host, car_link, car_storage, clock.clone(), NoLeds, rng),
+            fob: Fob::new(fob_host, fob_link, fob_storage, clock.clone(), NoLeds, TestRng::from_seed([3; 32])),
+            car_host: car_host_end,
+            fob_host: fob_host_end,
+            clock,
+        }
+    }
+
+    /// Runs both boards a millisecond at a time until they are idle again.
+    fn run(&mut self, sw1_pressed: bool) {
+        self.fob.poll(sw1_pressed);
+        for _ in 0..10_000 {
+            self.car.poll();
+            self.fob.poll(false);
+            if self.car.state() == CarState::Idle && self.fob.state() == FobState::Idle {
+                return;
+            }
+            self.clock.advance_us(1_000);
+        }
+        panic!("stuck in {:?} and {:?}", self.car.state(), self.fob.state());
+    }
+
+    /// Same as `run()`, with a relay between the boards that holds every
+    /// byte going from the car to the fob for `delay_us`.
+    fn run_relayed(&mut self, sw1_pressed: bool, delay_us: u64) {
+        self.fob.poll(sw1_pressed);
+        for _ in 0..10_000 {
+            self.car.poll();
+            self.clock.advance_us(delay_us);
+            self.fob.poll(false);
+            if self.car.state() == CarState::Idle && self.fob.state() == FobState::Idle {
+                return;
+            }
+            self.clock.advance_us(1_000);
+        }
+        panic!("stuck in {:?} and {:?}", self.car.state(), self.fob.state());
+    }
+
+    /// Runs both boards a millisecond at a time until `done` holds.
+    fn run_until(&mut self, done: impl Fn(&Bench) -> bool) {
+        for _ in 0..10_000 {
+            if done(self) {
+                return;
+            }
+            self.car.poll();
+            self.fob.poll(false);
+            self.clock.advance_us(1_000);
+        }
+        panic!("stuck in {:?} and {:?}", self.car.state(), self.fob.state());
+    }
+}
+
+/// Reads everything sent so far.
+fn drain(channel: &mut Channel) -> Vec<u8> {
+    let mut data = Vec::new();
+    while channel.avail() {
+        data.push(channel.readb());
+    }
+    data
+}
+
+/// A 64 byte message slot as the car sends it.
+fn slot(msg: &[u8]) -> Vec<u8> {
+    let mut data = msg.to_vec();
+    data.resize(64, 0xFF);
+    data
+}
+
+#[test]
+fn paired_fob_unlocks_car() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+    assert!(bench.clock.now_us() >= 500_000);
+}
+
+#[test]
+fn fob_with_wrong_key_does_not_unlock_car() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(OTHER_FOB_SECRET));
+    bench.run(true);
+    assert!(drain(&mut bench.car_host).is_empty());
+    assert!(bench.clock.now_us() >= 5_000_000);
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 1);
+}
+
+/// Presses SW1 and checks that the car turns the fob away at once.
+fn assert_refused(bench: &mut Bench) {
+    let start_us = bench.clock.now_us();
+    bench.run(true);
+    assert!(drain(&mut bench.car_host).is_empty());
+    assert!(bench.clock.now_us() - start_us < 100_000);
+}
+
+#[test]
+fn bad_response_locks_the_car_out_across_resets() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(OTHER_FOB_SECRET));
+    bench.run(true);
+    assert_refused(&mut bench);
+
+    // A reset does not help, not even with the right fob
+    let mut bench = Bench::new(bench.car.storage.clone(), paired_fob_image(FOB_SECRET));
+    assert_refused(&mut bench);
+
+    // Once the lockout is over the right fob unlocks and clears the count
+    bench.clock.advance_us(5_000_000);
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 0);
+}
+
+#[test]
+fn car_lockout_doubles_with_every_bad_response() {
+    for (fails, lockout_us) in [(2, 10_000_000), (3, 20_000_000), (20, 3_600_000_000)] {
+        let mut bench = Bench::new(with_stored_word(car_image(), CARMEM_UNLOCK_FAILS, fails), paired_fob_image(FOB_SECRET));
+        bench.clock.advance_us(lockout_us - 1_000);
+        assert_refused(&mut bench);
+        bench.clock.advance_us(1_000);
+        bench.run(true);
+        assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG), "{} fails", fails);
+    }
+}
+
+#[test]
+fn unpaired_fob_ignores_button() {
+    let mut bench = Bench::new(car_image(), unpaired_fob_image());
+    bench.fob.on_button();
+    assert_eq!(bench.fob.state(), FobState::Idle);
+    assert!(!bench.car.board_link.avail());
+}
+
+#[test]
+fn car_walks_through_unlock_states() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    send_link(&Message::UnlockReq, &mut bench.fob.board_link);
+    bench.car.poll();
+    assert_eq!(bench.car.state(), CarState::AwaitingPong);
+
+    // The host side of the car is not part of the protocol
+    for byte in frame(&Message::UnlockRst) {
+        bench.car.on_byte(Port::Host, byte);
+    }
+    assert_eq!(bench.car.state(), CarState::AwaitingPong);
+
+    // Hand the ping and challenge to the fob, and the response back to the car
+    bench.fob.on_button();
+    bench.run_until(|bench| bench.car.state() == CarState::Unlocking);
+    assert_eq!(bench.fob.state(), FobState::AwaitingGood);
+    assert!(drain(&mut bench.car_host).is_empty());
+
+    bench.clock.advance_us(500_000);
+    bench.car.on_tick();
+    assert_eq!(bench.car.state(), CarState::AwaitingFeatures);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+}
+
+/// A message as it goes over the board link.
+fn frame(msg: &Message) -> Vec<u8> {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let len = msg.encode_frame(&mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+/// Decodes everything sent to the host so far.
+fn drain_msgs(channel: &mut Channel) -> Vec<Message> {
+    let mut reader = MsgReader::new();
+    drain(channel).into_iter().filter_map(|byte| reader.push(byte)).map(Result::unwrap).collect()
+}
+
+/// Sends a message over the board link as the first thing a board says.
+fn send_link(msg: &Message, channel: &mut Channel) {
+    Link::new().send(msg, channel).unwrap();
+}
+
+/// Decodes everything sent over the board link so far, acknowledging each
+/// chunk back down the same channel.
+fn drain_link(channel: &mut Channel) -> Vec<Message> {
+    let mut link = Link::new();
+    drain(channel).into_iter().filter_map(|byte| link.on_byte(byte, 0, &mut *channel)).collect()
+}
+
+#[test]
+fn car_gives_up_on_silent_fob() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    send_link(&Message::UnlockReq, &mut bench.fob.board_link);
+    bench.car.poll();
+    assert_eq!(bench.car.state(), CarState::AwaitingPong);
+
+    bench.clock.advance_us(999_000);
+    bench.car.on_tick();
+    assert_eq!(bench.car.state(), CarState::AwaitingPong);
+    bench.clock.advance_us(1_000);
+    bench.car.on_tick();
+    assert_eq!(bench.car.state(), CarState::Idle);
+    assert_eq!(drain_link(&mut bench.fob.board_link), [Message::UnlockPing { ping: car_ping() }, Message::UnlockRst]);
+    assert!(drain(&mut bench.car_host).is_empty());
+}
+
+#[test]
+fn fob_gives_up_on_silent_car() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    bench.fob.on_button();
+    assert_eq!(bench.fob.state(), FobState::AwaitingPing);
+
+    bench.clock.advance_us(1_000_000);
+    bench.fob.on_tick();
+    assert_eq!(bench.fob.state(), FobState::Idle);
+    assert_eq!(drain_link(&mut bench.car.board_link), [Message::UnlockReq, Message::UnlockRst]);
+
+    // The car can still be unlocked afterwards
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+}
+
+#[test]
+fn partial_message_does_not_block_the_next_one() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    // Only the start of a chunk
+    for byte in &frame(&Message::LinkData { seq: 0, restart: true, len: LEN_CHUNK as u8, data: [0x62; LEN_CHUNK] })[..5] {
+        bench.car.on_byte(Port::BoardLink, *byte);
+    }
+    bench.clock.advance_us(500_000);
+    bench.car.on_tick();
+
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+}
+
+#[test]
+fn enabled_feature_is_sent_on_unlock() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    feature_package(2).send(&mut bench.fob_host);
+    bench.run(false);
+    assert_eq!(Message::decode(&drain(&mut bench.fob_host)), Ok(Message::HostSuccess));
+
+    bench.run(true);
+    let mut expected = slot(UNLOCK_MSG);
+    expected.extend(slot(FEAT_MSGS[1]));
+    assert_eq!(drain(&mut bench.car_host), expected);
+}
+
+/// Sends `msg` straight to the car through `peer` and returns whatever the
+/// car says back within the next 50ms.
+fn exchange(bench: &mut Bench, peer: &mut Link, msg: &Message) -> Vec<Message> {
+    peer.send(msg, &mut bench.fob.board_link).unwrap();
+    let mut received = Vec::new();
+    for _ in 0..50 {
+        bench.car.poll();
+        for byte in drain(&mut bench.fob.board_link) {
+            received.extend(peer.on_byte(byte, bench.clock.now_us(), &mut bench.fob.board_link));
+        }
+        peer.on_tick(bench.clock.now_us(), &mut bench.fob.board_link);
+        bench.clock.advance_us(1_000);
+    }
+    received
+}
+
+/// The first random bytes from an RNG seeded like the one in `Bench`, after
+/// the seed saved at boot.
+fn first_random(seed: u8) -> [u8; LEN_PING] {
+    let mut rng = TestRng::from_seed([seed; 32]);
+    rng.fill_bytes(&mut [0; LEN_RNG_SEED]);
+    let mut bytes = [0; LEN_PING];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// The UNLOCK_PING a fresh car in `Bench` sends first.
+fn car_ping() -> [u8; LEN_PING] {
+    first_random(2)
+}
+
+/// The UNLOCK_PONG a fresh fob in `Bench` answers with first.
+fn fob_pong() -> [u8; LEN_PING] {
+    first_random(3)
+}
+
+/// What a car sends for one unlock, as an eavesdropper would record it.
+#[derive(PartialEq, Eq, Debug)]
+struct Recorded {
+    ping: [u8; LEN_PING],
+    nonce: [u8; LEN_NONCE],
+    nonce_sig: [u8; 64],
+}
+
+/// Waits out any lockout, sends UNLOCK_REQ straight to the car, answers its
+/// ping as a fresh fob would and returns what the car sent, then calls the
+/// unlock off. `None` if the car sent UNLOCK_RST instead.
+fn challenge(bench: &mut Bench, peer: &mut Link) -> Option<Recorded> {
+    bench.clock.advance_us(3_600_000_000);
+    let ping = exchange(bench, peer, &Message::UnlockReq).into_iter().find_map(|msg| match msg {
+        Message::UnlockPing { ping } => Some(ping),
+        _ => None,
+    })?;
+    let chal = exchange(bench, peer, &Message::UnlockPong { pong: fob_pong() }).into_iter().find_map(|msg| match msg {
+        Message::UnlockChal { nonce, nonce_sig } => Some(Recorded { ping, nonce, nonce_sig }),
+        _ => None,
+    })?;
+    exchange(bench, peer, &Message::UnlockRst);
+    assert_eq!(bench.car.state(), CarState::Idle);
+    Some(chal)
+}
+
+/// The challenge counter at the front of a nonce.
+fn chal_count(chal: Option<Recorded>) -> Option<u32> {
+    chal.map(|chal| u32::from_be_bytes(chal.nonce[..4].try_into().unwrap()))
+}
+
+#[test]
+fn challenges_count_up_across_resets() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    let mut peer = Link::new();
+    for counter in 0..3 {
+        assert_eq!(chal_count(challenge(&mut bench, &mut peer)), Some(counter));
+    }
+    // The whole block was reserved before the first challenge
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_CHAL_COUNTER), 64);
+
+    // After a reset the car moves on to the next block
+    let mut bench = Bench::new(bench.car.storage.clone(), paired_fob_image(FOB_SECRET));
+    assert_eq!(chal_count(challenge(&mut bench, &mut Link::new())), Some(64));
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_CHAL_COUNTER), 128);
+}
+
+#[test]
+fn asking_for_challenges_over_and_over_is_locked_out() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    let mut peer = Link::new();
+    // Calling the unlock off before the challenge goes out costs nothing
+    for _ in 0..100 {
+        let ping = exchange(&mut bench, &mut peer, &Message::UnlockReq);
+        assert!(matches!(ping[..], [Message::UnlockPing { .. }]), "{:?}", ping);
+        exchange(&mut bench, &mut peer, &Message::UnlockRst);
+    }
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_CHAL_COUNTER), 0);
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 0);
+
+    // Walking away from a challenge counts as a bad response
+    assert_eq!(chal_count(challenge(&mut bench, &mut peer)), Some(0));
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 1);
+    for _ in 0..50 {
+        assert_eq!(exchange(&mut bench, &mut peer, &Message::UnlockReq), [Message::UnlockRst]);
+    }
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_CHAL_COUNTER), 64);
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 1);
+}
+
+#[test]
+fn same_rng_still_gives_new_challenges() {
+    // Both cars start from the same RNG seed
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    let first = challenge(&mut bench, &mut Link::new()).unwrap();
+    let mut bench = Bench::new(bench.car.storage.clone(), paired_fob_image(FOB_SECRET));
+    let second = challenge(&mut bench, &mut Link::new()).unwrap();
+    assert_eq!(first.nonce[4..], second.nonce[4..]);
+    assert_ne!(first.nonce, second.nonce);
+
+    // The unlock still goes through, once the lockout is over
+    bench.clock.advance_us(3_600_000_000);
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+}
+
+#[test]
+fn car_refuses_to_unlock_once_out_of_challenges() {
+    let mut bench = Bench::new(with_stored_word(car_image(), CARMEM_CHAL_COUNTER, u32::MAX - 1), paired_fob_image(FOB_SECRET));
+    let mut peer = Link::new();
+    assert_eq!(chal_count(challenge(&mut bench, &mut peer)), Some(u32::MAX - 1));
+    assert_eq!(challenge(&mut bench, &mut peer), None);
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_CHAL_COUNTER), u32::MAX);
+    assert_refused(&mut bench);
+}
+
+#[test]
+fn response_for_another_car_is_refused() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    let mut peer = Link::new();
+    let ping = exchange(&mut bench, &mut peer, &Message::UnlockReq);
+    let [Message::UnlockPing { ping }] = ping[..] else { panic!("{:?}", ping) };
+    let pong = [0x66; LEN_PING];
+    let chal = exchange(&mut bench, &mut peer, &Message::UnlockPong { pong });
+    let [Message::UnlockChal { nonce, nonce_sig }] = chal[..] else { panic!("{:?}", chal) };
+
+    // The right fob key, but signed as if for car 8
+    let fob_nonce = [0x77; LEN_NONCE];
+    let transcript = resp_transcript(&8u32.to_be_bytes(), &ping, &pong, &nonce, &nonce_sig, &fob_nonce);
+    let fob_secret = SecretKey::from_bytes(FOB_SECRET).unwrap();
+    let sig = fob_secret.sign(&transcript, ChaChaRng::from_seed([4; 32])).to_untagged_bytes();
+    exchange(&mut bench, &mut peer, &Message::UnlockResp { nonce: fob_nonce, nonce_sig: sig });
+    assert_eq!(bench.car.state(), CarState::Rejecting);
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 1);
+}
+
+/// Powers up a fob with the given EEPROM, presses SW1 and hands it the
+/// recorded ping and challenge. Returns what it sends back to the car and to
+/// the host.
+fn answer(fob_storage: MemStorage, recorded: &Recorded) -> (Vec<Message>, Vec<Message>) {
+    let clock = VirtualClock::new();
+    let (mut car_link, fob_link) = channel_pair();
+    let (fob_host, mut fob_host_end) = channel_pair();
+    let mut fob = Fob::new(fob_host, fob_link, fob_storage, clock.clone(), NoLeds, TestRng::from_seed([3; 32]));
+    let mut car = Link::new();
+    fob.on_button();
+    let mut received = Vec::new();
+    let ping = Message::UnlockPing { ping: recorded.ping };
+    let chal = Message::UnlockChal { nonce: recorded.nonce, nonce_sig: recorded.nonce_sig };
+    for msg in [ping, chal] {
+        car.send(&msg, &mut car_link).unwrap();
+        for _ in 0..50 {
+            fob.poll(false);
+            for byte in drain(&mut car_link) {
+                received.extend(car.on_byte(byte, clock.now_us(), &mut car_link));
+            }
+            car.on_tick(clock.now_us(), &mut car_link);
+            clock.advance_us(1_000);
+        }
+    }
+    (received, drain_msgs(&mut fob_host_end))
+}
+
+#[test]
+fn fob_refuses_replayed_challenge() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    let recorded = challenge(&mut bench, &mut Link::new()).unwrap();
+
+    // Before the fob has answered anything newer, the challenge is still good.
+    // A fresh fob picks the same UNLOCK_PONG as the recording, so the car's
+    // signature checks out every time
+    let (received, host) = answer(bench.fob.storage.clone(), &recorded);
+    let pong = Message::UnlockPong { pong: fob_pong() };
+    assert!(matches!(&received[..], [Message::UnlockReq, p, Message::UnlockResp { .. }] if *p == pong), "{:?}", received);
+    assert_eq!(host, []);
+
+    // Once it has, the recorded challenge is refused, even after a reset
+    bench.clock.advance_us(3_600_000_000);
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+    assert_eq!(stored_word(&bench.fob.storage, FOBMEM_NEXT_CHAL), 2);
+    let (received, host) = answer(bench.fob.storage.clone(), &recorded);
+    assert_eq!(received, [Message::UnlockReq, pong, Message::UnlockRst]);
+    assert_eq!(host, [Message::HostReplay { chal_counter: [0; 4] }]);
+
+    // Newer challenges still work
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+}
+
+#[test]
+fn pairing_hands_over_the_replay_floor() {
+    let paired = with_stored_word(paired_fob_image(FOB_SECRET), FOBMEM_NEXT_CHAL, 70);
+    let (storage, reply, _) = pair_from(paired, PIN);
+    assert_eq!(reply, Message::HostSuccess);
+    assert_eq!(stored_word(&storage, FOBMEM_NEXT_CHAL), 70);
+}
+
+#[test]
+fn fob_refuses_challenge_from_another_car() {
+    let mut fob_storage = paired_fob_image(FOB_SECRET);
+    fob_storage.image_mut()[0x200..0x204].copy_from_slice(&8u32.to_be_bytes());
+    let mut bench = Bench::new(car_image(), fob_storage);
+    bench.run(true);
+    assert!(drain(&mut bench.car_host).is_empty());
+    // The fob walked away from the challenge, which the car counts the same
+    // as a bad response
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 1);
+}
+
+#[test]
+fn boot_replaces_the_rng_seed() {
+    let bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    let car_seed = &bench.car.storage.image()[0x1E0..0x200];
+    let fob_seed = &bench.fob.storage.image()[0x1E0..0x200];
+    assert_ne!(car_seed, &[0xFF; LEN_RNG_SEED]);
+    assert_ne!(fob_seed, &[0xFF; LEN_RNG_SEED]);
+    assert_ne!(car_seed, fob_seed);
+}
+
+#[test]
+fn calibration_sets_the_round_trip_limit() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_RTT_LIMIT), u32::MAX);
+    Message::Calibrate.send(&mut bench.car_host);
+    bench.run(true);
+
+    // UNLOCK_PONG came back a millisecond after UNLOCK_PING, plus half again
+    let mut expected = vec![0xBE];
+    expected.extend(24_000u32.to_be_bytes());
+    expected.extend(slot(UNLOCK_MSG));
+    assert_eq!(drain(&mut bench.car_host), expected);
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_RTT_LIMIT), 24_000);
+
+    // Once calibrated, the limit stays
+    Message::Calibrate.send(&mut bench.car_host);
+    bench.run(false);
+    assert_eq!(drain_msgs(&mut bench.car_host), [Message::HostFailure]);
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_RTT_LIMIT), 24_000);
+}
+
+#[test]
+fn calibration_does_not_time_a_queued_ping() {
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    Message::Calibrate.send(&mut bench.car_host);
+
+    // An unlock that times out. The fob gets the UNLOCK_RST, but its
+    // acknowledgement is lost and the car keeps resending
+    send_link(&Message::UnlockReq, &mut bench.fob.board_link);
+    bench.car.poll();
+    bench.run_until(|bench| bench.car.state() == CarState::Idle);
+    bench.fob.poll(false);
+    drain(&mut bench.car.board_link);
+
+    // The fob's UNLOCK_REQ comes in while the car still waits on that
+    bench.fob.on_button();
+    bench.car.poll();
+    assert_eq!(bench.car.state(), CarState::SendingPing);
+    bench.run(false);
+
+    // The ping only went out once the link was free again
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_RTT_LIMIT), 24_000);
+    assert_eq!(drain(&mut bench.car_host)[1..5], 24_000u32.to_be_bytes());
+}
+
+#[test]
+fn relayed_fob_is_refused_once_calibrated() {
+    // Without a limit the car does not mind a slow fob
+    let mut bench = Bench::new(car_image(), paired_fob_image(FOB_SECRET));
+    bench.run_relayed(true, 2_000);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+
+    let mut bench = Bench::new(with_stored_word(car_image(), CARMEM_RTT_LIMIT, 24_000), paired_fob_image(FOB_SECRET));
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+    bench.run_relayed(true, 2_000);
+    assert!(drain(&mut bench.car_host).is_empty());
+    assert_eq!(stored_word(&bench.car.storage, CARMEM_UNLOCK_FAILS), 1);
+}
+
+const WRONG_PIN: [u8; 3] = [0x65, 0x43, 0x21];
+
+/// Runs PAIR_REQ on a paired fob wired to an unpaired fob. Returns the
+/// unpaired fob's EEPROM, what it told the host and how long pairing took.
+fn pair(pin: [u8; 3]) -> (MemStorage, Message, u64) {
+    pair_from(paired_fob_image(FOB_SECRET), pin)
+}
+
+/// Same as `pair()`, starting from the given paired fob EEPROM.
+fn pair_from(paired_storage: MemStorage, pin: [u8; 3]) -> (MemStorage, Message, u64) {
+    let run = run_pairing(paired_storage, 0, pin);
+    assert_eq!(run.paired_replies, []);
+    let [reply] = &run.unpaired_replies[..] else { panic!("{:?}", run.unpaired_replies) };
+    (run.unpaired, reply.clone(), run.busy_us)
+}
+
+/// Both fobs after a PAIR_REQ, and what each told its host.
+struct PairRun {
+    paired: MemStorage,
+    unpaired: MemStorage,
+    paired_replies: Vec<Message>,
+    unpaired_replies: Vec<Message>,
+    busy_us: u64,
+    /// Every byte that went over the link, both ways
+    wire: Vec<u8>,
+}
+
+/// What a PBKDF2 iteration costs on the board.
+const US_KDF_ITERATION: u64 = 100;
+
+/// What a pass through the main loop can cost on the board while pairing:
+/// moving a message over the link, and one `PIN_HASH_STEP` of the PIN hash
+/// on either fob. The fobs run side by side, so a pass of both costs the
+/// same.
+const US_PAIRING_PASS: u64 = 1_000 + PIN_HASH_STEP as u64 * US_KDF_ITERATION;
+
+/// Powers up a paired fob with the given EEPROM next to an unpaired fob,
+/// waits `wait_us`, then runs PAIR_REQ.
+fn run_pairing(paired_storage: MemStorage, wait_us: u64, pin: [u8; 3]) -> PairRun {
+    run_pairing_into(paired_storage, unpaired_fob_image(), wait_us, pin)
+}
+
+/// Same as `run_pairing()`, with the given unpaired fob EEPROM.
+fn run_pairing_into(paired_storage: MemStorage, unpaired_storage: MemStorage, wait_us: u64, pin: [u8; 3]) -> PairRun {
+    let mut bench = PairBench::new(paired_storage, unpaired_storage);
+    bench.clock.advance_us(wait_us);
+    let busy_us = bench.run(pin);
+    PairRun {
+        paired_replies: drain_msgs(&mut bench.paired_host),
+        unpaired_replies: drain_msgs(&mut bench.unpaired_host),
+        paired: bench.paired.storage,
+        unpaired: bench.unpaired.storage,
+        busy_us,
+        wire: bench.wire,
+    }
+}
+
+/// A paired and an unpaired fob wired together, sharing one clock.
+struct PairBench {
+    paired: TestFob,
+    unpaired: TestFob,
+    paired_wire: Channel,
+    unpaired_wire: Channel,
+    paired_host: Channel,
+    unpaired_host: Channel,
+    clock: VirtualClock,
+    /// Every byte that went over the link, both ways
+    wire: Vec<u8>,
+}
+
+impl PairBench {
+    fn new(paired_storage: MemStorage, unpaired_storage: MemStorage) -> PairBench {
+        let clock = VirtualClock::new();
+        let (paired_link, paired_wire) = channel_pair();
+        let (unpaired_link, unpaired_wire) = channel_pair();
+        let (paired_host, paired_host_end) = channel_pair();
+        let (unpaired_host, unpaired_host_end) = channel_pair();
+        PairBench {
+            paired: Fob::new(paired_host, paired_link, paired_storage, clock.clone(), NoLeds, TestRng::from_seed([3; 32])),
+            unpaired: Fob::new(unpaired_host, unpaired_link, unpaired_storage, clock.clone(), NoLeds, TestRng::from_seed([4; 32])),
+            paired_wire,
+            unpaired_wire,
+            paired_host: paired_host_end,
+            unpaired_host: unpaired_host_end,
+            clock,
+            wire: Vec::new(),
+        }
+    }
+
+    /// Runs PAIR_REQ until both fobs are idle again. Returns how long that
+    /// took.
+    fn run(&mut self, pin: [u8; 3]) -> u64 {
+        let start_us = self.clock.now_us();
+        Message::PairReq { pin }.send(&mut self.paired_host);
+        self.paired.poll(false);
+        for _ in 0..10_000 {
+            self.clock.advance_us(US_PAIRING_PASS);
+            self.paired.poll(false);
+            // Pass the link bytes across, keeping a copy
+            let sent = drain(&mut self.paired_wire);
+            self.unpaired_wire.write(&sent);
+            self.wire.extend(sent);
+            self.unpaired.poll(false);
+            let sent = drain(&mut self.unpaired_wire);
+            self.paired_wire.write(&sent);
+            self.wire.extend(sent);
+            if self.paired.state() == FobState::Idle && self.unpaired.state() == FobState::Idle {
+                break;
+            }
+        }
+        self.clock.now_us() - start_us
+    }
+}
+
+#[test]
+fn pairing_with_correct_pin_gives_working_fob() {
+    // Done within the second, with the time the board takes to work on the
+    // PIN hash and pass PAIR_FIN along
+    let (storage, reply, busy_us) = pair(PIN);
+    assert_eq!(reply, Message::HostSuccess);
+    assert!(busy_us < 1_000_000, "{}", busy_us);
+    assert_ne!(storage.image()[0x400..0x404], [0, 0, 0, 0]);
+    let salt = &storage.image()[0x140..0x14C];
+    let secret_enc = storage.image()[0x180..0x1C4].try_into().unwrap();
+    let keys = pin_hash(&PIN, salt, PIN_HASH_ITERATIONS);
+    assert_eq!(unwrap(secret_enc, &keys.wrap_key), Ok(FOB_SECRET));
+    assert_eq!(storage.image()[0x15C..0x160], PIN_HASH_ITERATIONS.to_be_bytes());
+    assert_eq!(storage.image()[0x160..0x180], keys.check);
+    assert_eq!(stored_word(&storage, FOBMEM_NEXT_CHAL), 0);
+
+    let mut bench = Bench::new(car_image(), storage);
+    bench.run(true);
+    assert_eq!(drain(&mut bench.car_host), slot(UNLOCK_MSG));
+}
+
+#[test]
+fn pairing_with_wrong_pin_fails_slowly() {
+    let (storage, reply, busy_us) = pair(WRONG_PIN);
+    assert_eq!(reply, Message::HostFailure);
+    assert!(busy_us >= 5_000_000);
+    assert_eq!(storage.image()[0x400..0x404], [0, 0, 0, 0]);
+}
+
+#[test]
+fn wrong_pins_lock_the_fob_out_across_resets() {
+    // Every run powers the paired fob up again from the EEPROM it left
+    let mut storage = paired_fob_image(FOB_SECRET);
+    for fails in 1..=3 {
+        let run = run_pairing(storage, 0, WRONG_PIN);
+        assert_eq!(run.unpaired_replies, [Message::HostFailure]);
+        assert_eq!(stored_word(&run.paired, FOBMEM_PIN_FAILS), fails);
+        storage = run.paired;
+    }
+
+    let run = run_pairing(storage, 0, PIN);
+    assert_eq!(run.paired_replies, [Message::HostLocked { ms_left: 10_000u32.to_be_bytes() }]);
+    assert_eq!(run.unpaired_replies, []);
+    assert!(run.busy_us < 10_000);
+    assert_eq!(stored_word(&run.paired, FOBMEM_PIN_FAILS), 3);
+}
+
+#[test]
+fn lockout_doubles_with_every_wrong_pin() {
+    for (fails, ms_left) in [(4, 20_000u32), (5, 40_000), (6, 80_000), (100, 3_600_000), (u32::MAX, 3_600_000)] {
+        let run = run_pairing(with_stored_word(paired_fob_image(FOB_SECRET), FOBMEM_PIN_FAILS, fails), 0, PIN);
+        assert_eq!(run.paired_replies, [Message::HostLocked { ms_left: ms_left.to_be_bytes() }], "{} fails", fails);
+    }
+
+    let run = run_pairing(with_stored_word(paired_fob_image(FOB_SECRET), FOBMEM_PIN_FAILS, 4), 5_000_000, PIN);
+    assert_eq!(run.paired_replies, [Message::HostLocked { ms_left: 15_000u32.to_be_bytes() }]);
+}
+
+#[test]
+fn right_pin_after_the_lockout_clears_the_count() {
+    let run = run_pairing(with_stored_word(paired_fob_image(FOB_SECRET), FOBMEM_PIN_FAILS, 4), 20_000_000, PIN);
+    assert_eq!(run.paired_replies, []);
+    assert_eq!(run.unpaired_replies, [Message::HostSuccess]);
+    assert_eq!(stored_word(&run.paired, FOBMEM_PIN_FAILS), 0);
+    assert_eq!(stored_word(&run.unpaired, FOBMEM_PIN_FAILS), 0);
+}
+
+#[test]
+fn pin_never_crosses_the_link_in_the_clear() {
+    let run = run_pairing(paired_fob_image(FOB_SECRET), 0, PIN);
+    assert_eq!(run.unpaired_replies, [Message::HostSuccess]);
+    assert!(!run.wire.windows(PIN.len()).any(|w| w == PIN));
+}
+
+#[test]
+fn pin_hash_uses_the_stored_iteration_count() {
+    let mut paired_storage = paired_fob_image(FOB_SECRET);
+    let image = paired_storage.image_mut();
+    let keys = pin_hash(&PIN, &[0x5A; 12], 100);
+    image[0x15C..0x160].copy_from_slice(&100u32.to_be_bytes());
+    image[0x160..0x180].copy_from_slice(&keys.check);
+    image[0x180..0x1C4].copy_from_slice(&wrap(&FOB_SECRET, &keys.wrap_key, &[0xA5; 12]));
+    let (_, reply, _) = pair_from(paired_storage, PIN);
+    assert_eq!(reply, Message::HostSuccess);
+}
+
+/// Powers up a fob with the given EEPROM and returns the EEPROM.
+fn boot(storage: MemStorage) -> MemStorage {
+    let (host, _) = channel_pair();
+    let (board_link, _) = channel_pair();
+    Fob::new(host, board_link, storage, VirtualClock::new(), NoLeds, TestRng::from_seed([4; 32])).storage
+}
+
+#[test]
+fn unpaired_fob_measures_its_iteration_count_at_first_boot() {
+    // The virtual clock does not move while hashing, which gives the default
+    let storage = boot(unpaired_fob_image());
+    assert_eq!(storage.image()[0x15C..0x160], PIN_HASH_ITERATIONS.to_be_bytes());
+    // A count from an earlier boot is kept
+    let mut storage = unpaired_fob_image();
+    storage.image_mut()[0x15C..0x160].copy_from_slice(&2000u32.to_be_bytes());
+    assert_eq!(boot(storage).image()[0x15C..0x160], 2000u32.to_be_bytes());
+    // A paired fob's count goes with its PIN_HASH
+    let mut storage = paired_fob_image(FOB_SECRET);
+    storage.image_mut()[0x15C..0x160].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert_eq!(boot(storage).image()[0x15C..0x160], u32::MAX.to_be_bytes());
+}
+
+#[test]
+fn pairing_hashes_with_the_measured_iteration_count() {
+    let mut unpaired_storage = unpaired_fob_image();
+    unpaired_storage.image_mut()[0x15C..0x160].copy_from_slice(&2000u32.to_be_bytes());
+    let run = run_pairing_into(paired_fob_image(FOB_SECRET), unpaired_storage, 0, PIN);
+    assert_eq!(run.unpaired_replies, [Message::HostSuccess]);
+    let keys = pin_hash(&PIN, &run.unpaired.image()[0x140..0x14C], 2000);
+    assert_eq!(run.unpaired.image()[0x15C..0x160], 2000u32.to_be_bytes());
+    assert_eq!(run.unpaired.image()[0x160..0x180], keys.check);
+}
+
+#[test]
+fn pairing_from_tampered_secret_enc_fails() {
+    let mut paired_storage = paired_fob_image(FOB_SECRET);
+    paired_storage.image_mut()[0x1A0] ^= 0x01;
+    let (storage, reply, busy_us) = pair_from(paired_storage, PIN);
+    assert_eq!(reply, Message::HostFailure);
+    assert!(busy_us >= 5_000_000);
+    assert_eq!(storage.image()[0x400..0x404], [0, 0, 0, 0]);
+}
+
+#[test]
+fn tampered_secret_enc_locks_the_fob_out_without_a_reset() {
+    let mut paired_storage = with_stored_word(paired_fob_image(FOB_SECRET), FOBMEM_PIN_FAILS, 2);
+    paired_storage.image_mut()[0x1A0] ^= 0x01;
+    let mut bench = PairBench::new(paired_storage, unpaired_fob_image());
+    bench.run(PIN);
+    assert_eq!(drain_msgs(&mut bench.unpaired_host), [Message::HostFailure]);
+    assert_eq!(stored_word(&bench.paired.storage, FOBMEM_PIN_FAILS), 3);
+
+    // The fob goes by the count it just stored, as it would after a reset
+    bench.run(PIN);
+    let [Message::HostLocked { .. }] = drain_msgs(&mut bench.paired_host)[..] else { panic!("not locked out") };
+}
+
+/// An unpaired fob with nothing else on its host port.
+fn unpaired_fob(clock: &VirtualClock) -> (TestFob, Channel, Channel) {
+    let (paired_link, unpaired_link) = channel_pair();
+    let (unpaired_host, unpaired_host_end) = channel_pair();
+    let rng = TestRng::from_seed([4; 32]);
+    let unpaired = Fob::new(unpaired_host, unpaired_link, unpaired_fob_image(), clock.clone(), NoLeds, rng);
+    (unpaired, paired_link, unpaired_host_end)
+}
+
+/// The secrets a paired fob would hand over.
+fn pair_secrets() -> PairSecrets {
+    PairSecrets {
+        fob_secret: FOB_SECRET,
+        car_id: CAR_ID.to_be_bytes(),
+        feature_sigs: [[0xFF; 64]; 3],
+        car_public: public(CAR_SECRET),
+        next_chal: [0; 4],
+    }
+}
+
+/// The ephemeral key the test stands in for the paired fob with.
+const EPH_SECRET: [u8; 32] = [0x55; 32];
+
+/// Stands in for the paired fob: sends PAIR_SYN with `syn_public`, then
+/// seals the PIN (if `send_pin`) and the secrets with whatever key `key`
+/// makes of the PAIR_ACK, if any. `mangle` may change each write bound for
+/// the unpaired fob. Returns the peer's link and what it received.
+fn pair_with(
+    unpaired: &mut TestFob,
+    paired_link: &mut Channel,
+    clock: &VirtualClock,
+    syn_public: [u8; 64],
+    key: impl Fn(&[u8; 64]) -> Option<[u8; 32]>,
+    send_pin: bool,
+    mut mangle: impl FnMut(&mut Vec<u8>),
+) -> (Link, Vec<Message>) {
+    let (mut peer_end, mut wire) = channel_pair();
+    let mut peer = Link::new();
+    peer.send(&Message::PairSyn { eph_public: syn_public }, &mut peer_end).unwrap();
+
+    let mut received = Vec::new();
+    for _ in 0..2_000 {
+        let mut data = drain(&mut wire);
+        mangle(&mut data);
+        paired_link.write(&data);
+        unpaired.poll(false);
+        for byte in drain(paired_link) {
+            let Some(msg) = peer.on_byte(byte, clock.now_us(), &mut peer_end) else { continue };
+            if let Message::PairAck { eph_public } = &msg {
+                if let Some(key) = key(eph_public) {
+                    if send_pin {
+                        peer.send(&seal_pin(&PIN, &key), &mut peer_end).unwrap();
+                    }
+                    peer.send(&pair_secrets().seal(&key), &mut peer_end).unwrap();
+                }
+            }
+            received.push(msg);
+        }
+        peer.on_tick(clock.now_us(), &mut peer_end);
+        if peer.idle() && unpaired.state() == FobState::Idle {
+            break;
+        }
+        clock.advance_us(1_000);
+    }
+    (peer, received)
+}
+
+/// The key both fobs should agree on.
+fn agreed_key(ack_public: &[u8; 64]) -> Option<[u8; 32]> {
+    let eph_secret = SecretKey::from_bytes(EPH_SECRET).unwrap();
+    pair_key(&eph_secret, ack_public, &public(EPH_SECRET), ack_public)
+}
+
+#[test]
+fn unpaired_fob_gives_up_without_pair_fin() {
+    let clock = VirtualClock::new();
+    let (mut unpaired, mut paired_link, mut unpaired_host_end) = unpaired_fob(&clock);
+
+    let (_, received) = pair_with(&mut unpaired, &mut paired_link, &clock, public(EPH_SECRET), |_| None, true, |_| {});
+    assert_eq!(unpaired.state(), FobState::Idle);
+    assert!(clock.now_us() >= 1_000_000);
+    assert!(matches!(received[..], [Message::PairAck { .. }, Message::PairRst]));
+    assert_eq!(drain_msgs(&mut unpaired_host_end), [Message::HostFailure]);
+}
+
+#[test]
+fn unpaired_fob_ignores_pair_syn_with_bad_key() {
+    let clock = VirtualClock::new();
+    let (mut unpaired, mut paired_link, mut unpaired_host_end) = unpaired_fob(&clock);
+
+    let (peer, received) = pair_with(&mut unpaired, &mut paired_link, &clock, [0xFF; 64], agreed_key, true, |_| {});
+    assert!(peer.idle());
+    assert_eq!(received, []);
+    assert_eq!(drain_msgs(&mut unpaired_host_end), []);
+}
+
+#[test]
+fn pair_fin_under_another_key_is_refused() {
+    let clock = VirtualClock::new();
+    let (mut unpaired, mut paired_link, mut unpaired_host_end) = unpaired_fob(&clock);
+
+    let (peer, _) = pair_with(&mut unpaired, &mut paired_link, &clock, public(EPH_SECRET), |_| Some([0x66; 32]), true, |_| {});
+    assert!(peer.idle());
+    assert_eq!(drain_msgs(&mut unpaired_host_end), [Message::HostFailure]);
+    assert_eq!(unpaired.storage.image()[0x100..0x120], [0xFF; 32]);
+    assert_eq!(unpaired.storage.image()[0x400..0x404], [0, 0, 0, 0]);
+}
+
+#[test]
+fn pair_fin_without_pair_pin_is_refused() {
+    let clock = VirtualClock::new();
+    let (mut unpaired, mut paired_link, mut unpaired_host_end) = unpaired_fob(&clock);
+
+    let (peer, _) = pair_with(&mut unpaired, &mut paired_link, &clock, public(EPH_SECRET), agreed_key, false, |_| {});
+    assert!(peer.idle());
+    assert_eq!(drain_msgs(&mut unpaired_host_end), [Message::HostFailure]);
+    assert_eq!(unpaired.storage.image()[0x400..0x404], [0, 0, 0, 0]);
+}
+
+#[test]
+fn mangled_pair_fin_chunk_is_sent_again() {
+    let clock = VirtualClock::new();
+    let (mut unpaired, mut paired_link, mut unpaired_host_end) = unpaired_fob(&clock);
+
+    // The PAIR_SYN goes in chunks 0 to 10 and the PAIR_PIN in 11 to 14, so
+    // chunk 17 is the PAIR_FIN's third
+    let mut mangled = false;
+    let mangle = |data: &mut Vec<u8>| {
+        if !mangled && data.starts_with(&[0x7E, 0, 8, 0x70, 17]) {
+            data[6] ^= 0x80;
+            mangled = true;
+        }
+    };
+    let (peer, _) = pair_with(&mut unpaired, &mut paired_link, &clock, public(EPH_SECRET), agreed_key, true, mangle);
+
+    assert!(mangled);
+    assert!(peer.idle());
+    assert_eq!(drain_msgs(&mut unpaired_host_end), [Message::HostSuccess]);
+    assert_eq!(unpaired.storage.image()[0x100..0x120], FOB_SECRET);
+    assert_eq!(unpaired.storage.image()[0x200..0x204], CAR_ID.to_be_bytes());
+}
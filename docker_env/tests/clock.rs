@@ -0,0 +1,38 @@
+#![cfg(feature = "std")]
+
+use tiva::{host::VirtualClock, Clock};
+
+#[test]
+fn virtual_clock_follows_delay_timer() {
+    let mut clock = VirtualClock::new();
+    let handle = clock.clone();
+
+    // Same pattern as the paired fob: 1s transaction, PAIR_ACK checked at 800ms
+    clock.start_delay_timer_us(1_000_000);
+    assert_eq!(clock.get_remaining_us_delay_timer(), 1_000_000);
+    clock.wait_remaining_us_delay_timer(200_000);
+    assert_eq!(handle.now_us(), 800_000);
+    assert_eq!(clock.get_remaining_us_delay_timer(), 200_000);
+
+    clock.wait_delay_timer();
+    assert_eq!(handle.now_us(), 1_000_000);
+    assert_eq!(clock.get_remaining_us_delay_timer(), 0);
+
+    clock.sleep_us(4_000_000);
+    assert_eq!(handle.now_us(), 5_000_000);
+    assert_eq!(clock.get_tick_timer(), 5_000_000 * 16);
+}
+
+#[test]
+fn virtual_clock_can_be_advanced_from_outside() {
+    let mut clock = VirtualClock::new();
+    let handle = clock.clone();
+
+    clock.start_delay_timer_us(500_000);
+    handle.advance_us(600_000);
+    assert_eq!(clock.get_remaining_us_delay_timer(), 0);
+
+    // An expired delay timer does not wait
+    clock.wait_delay_timer();
+    assert_eq!(handle.now_us(), 600_000);
+}
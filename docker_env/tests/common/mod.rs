@@ -0,0 +1,148 @@
+//! EEPROM images and feature packages laid out like the ones from the
+//! deployment and host tool scripts, for running the car and fob off the
+//! board.
+
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use p256_cortex_m4::SecretKey;
+use rand_chacha::{
+    rand_core::{CryptoRng, Error, RngCore, SeedableRng},
+    ChaChaRng,
+};
+use tiva::{
+    host::MemStorage,
+    pin::{pin_hash, PIN_HASH_ITERATIONS},
+    protocol::Message,
+    rng::EventRng,
+    wrap::wrap,
+    Event, Led, Leds, Signer,
+};
+
+pub const FOB_SECRET: [u8; 32] = [0x11; 32];
+pub const CAR_SECRET: [u8; 32] = [0x22; 32];
+pub const MAN_SECRET: [u8; 32] = [0x33; 32];
+pub const OTHER_FOB_SECRET: [u8; 32] = [0x44; 32];
+
+pub const CAR_ID: u32 = 7;
+pub const PIN: [u8; 3] = [0x12, 0x34, 0x56];
+pub const UNLOCK_MSG: &[u8] = b"unlocked";
+pub const FEAT_MSGS: [&[u8]; 3] = [b"feature 1", b"feature 2", b"feature 3"];
+
+/// LEDs that do nothing.
+pub struct NoLeds;
+
+impl Leds for NoLeds {
+    fn set_led(&mut self, _led: Led, _on: bool) {}
+}
+
+/// An RNG seeded once and left alone, so runs can be repeated.
+pub struct TestRng(ChaChaRng);
+
+impl TestRng {
+    pub fn from_seed(seed: [u8; 32]) -> TestRng {
+        TestRng(ChaChaRng::from_seed(seed))
+    }
+}
+
+impl RngCore for TestRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for TestRng {}
+
+impl EventRng for TestRng {
+    fn add_event(&mut self, _event: Event, _ticks: u64) {}
+
+    fn add_seed(&mut self, _seed: &[u8]) {}
+}
+
+pub fn public(secret: [u8; 32]) -> [u8; 64] {
+    SecretKey::from_bytes(secret).unwrap().public_key().to_untagged_bytes()
+}
+
+/// Same as build_car_eeprom.py, with messages filled in.
+pub fn car_image() -> MemStorage {
+    let mut storage = MemStorage::new();
+    let image = storage.image_mut();
+    image[0x100..0x120].copy_from_slice(&CAR_SECRET);
+    image[0x120..0x160].copy_from_slice(&public(MAN_SECRET));
+    image[0x160..0x1A0].copy_from_slice(&public(FOB_SECRET));
+    image[0x1A0..0x1A8].copy_from_slice(&[0; 8]);
+    image[0x200..0x204].copy_from_slice(&CAR_ID.to_be_bytes());
+    for (i, msg) in FEAT_MSGS.iter().enumerate() {
+        let address = 0x780 - i * 0x40;
+        image[address..address + msg.len()].copy_from_slice(msg);
+    }
+    image[0x7C0..0x7C0 + UNLOCK_MSG.len()].copy_from_slice(UNLOCK_MSG);
+    storage
+}
+
+/// Same as build_fob_eeprom.py for a paired fob.
+pub fn paired_fob_image(fob_secret: [u8; 32]) -> MemStorage {
+    let salt = [0x5A; 12];
+    let keys = pin_hash(&PIN, &salt, PIN_HASH_ITERATIONS);
+    let secret_enc = wrap(&fob_secret, &keys.wrap_key, &[0xA5; 12]);
+
+    let mut storage = MemStorage::new();
+    let image = storage.image_mut();
+    image[0x100..0x120].copy_from_slice(&fob_secret);
+    image[0x140..0x14C].copy_from_slice(&salt);
+    image[0x14C..0x154].copy_from_slice(&[0; 8]);
+    image[0x15C..0x160].copy_from_slice(&PIN_HASH_ITERATIONS.to_be_bytes());
+    image[0x160..0x180].copy_from_slice(&keys.check);
+    image[0x180..0x1C4].copy_from_slice(&secret_enc);
+    image[0x200..0x204].copy_from_slice(&CAR_ID.to_be_bytes());
+    image[0x300..0x340].copy_from_slice(&public(CAR_SECRET));
+    image[0x400..0x404].copy_from_slice(&[0, 0, 0, 1]);
+    storage
+}
+
+/// Same as build_fob_eeprom.py for an unpaired fob.
+pub fn unpaired_fob_image() -> MemStorage {
+    let mut storage = MemStorage::new();
+    let image = storage.image_mut();
+    image[0x140..0x14C].copy_from_slice(&[0xA5; 12]);
+    image[0x14C..0x154].copy_from_slice(&[0; 8]);
+    image[0x400..0x404].copy_from_slice(&[0, 0, 0, 0]);
+    storage
+}
+
+/// Reads an EEPROM word the way the car and fob do, at one of the
+/// `CARMEM_`/`FOBMEM_` addresses.
+pub fn stored_word(storage: &MemStorage, address: u32) -> u32 {
+    let address = address as usize;
+    u32::from_ne_bytes(storage.image()[address..address + 4].try_into().unwrap())
+}
+
+/// Same EEPROM with the word at `address` set to `value`.
+pub fn with_stored_word(mut storage: MemStorage, address: u32, value: u32) -> MemStorage {
+    let address = address as usize;
+    storage.image_mut()[address..address + 4].copy_from_slice(&value.to_ne_bytes());
+    storage
+}
+
+/// ENAB_FEAT with a package like the ones from package_tool.
+pub fn feature_package(feature: u32) -> Message {
+    let car_id = CAR_ID.to_be_bytes();
+    let feature_num = feature.to_be_bytes();
+    let mut package = car_id.to_vec();
+    package.extend_from_slice(&feature_num);
+    let man_secret = SecretKey::from_bytes(MAN_SECRET).unwrap();
+    let signature = man_secret.sign(&package, ChaChaRng::from_seed([1; 32]));
+    Message::EnabFeat { car_id, feature_num, feature_sig: signature.to_untagged_bytes() }
+}
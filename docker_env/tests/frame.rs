@@ -0,0 +1,108 @@
+#![cfg(feature = "std")]
+
+use tiva::frame::{crc32, FrameError, FrameReader, MAX_FRAME_LEN, SOF};
+use tiva::protocol::{DecodeError, Message};
+
+fn frame(msg: &Message) -> Vec<u8> {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let len = msg.encode_frame(&mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+/// Feeds bytes to a reader and collects everything it hands back.
+fn read_all(reader: &mut FrameReader, data: &[u8]) -> Vec<Result<Message, FrameError>> {
+    data.iter().filter_map(|byte| reader.push(*byte)).collect()
+}
+
+fn chal() -> Message {
+    Message::UnlockChal { nonce: [7; 16], nonce_sig: [8; 64] }
+}
+
+#[test]
+fn crc32_matches_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn frame_layout() {
+    let data = frame(&Message::PairReq { pin: [0x12, 0x34, 0x56] });
+    assert_eq!(data[..7], [SOF, 0, 3, 0x40, 0x12, 0x34, 0x56]);
+    assert_eq!(data[7..], crc32(&data[1..7]).to_be_bytes());
+    assert_eq!(data.len(), Message::PairReq { pin: [0; 3] }.frame_len());
+}
+
+#[test]
+fn frames_survive_a_round_trip() {
+    let mut reader = FrameReader::new();
+    let mut data = frame(&chal());
+    data.extend(frame(&Message::UnlockGood));
+    assert_eq!(read_all(&mut reader, &data), [Ok(chal()), Ok(Message::UnlockGood)]);
+}
+
+#[test]
+fn noise_between_frames_is_skipped() {
+    let mut reader = FrameReader::new();
+    let mut data = vec![0x00, 0xFF, 0x60];
+    data.extend(frame(&Message::UnlockReq));
+    data.extend([0x42, 0x42]);
+    data.extend(frame(&Message::UnlockRst));
+    assert_eq!(read_all(&mut reader, &data), [Ok(Message::UnlockReq), Ok(Message::UnlockRst)]);
+}
+
+#[test]
+fn mangled_frame_is_rejected() {
+    let mut reader = FrameReader::new();
+    let mut data = frame(&chal());
+    data[20] ^= 0x01;
+    data.extend(frame(&Message::UnlockGood));
+    assert_eq!(read_all(&mut reader, &data), [Err(FrameError::BadCrc), Ok(Message::UnlockGood)]);
+}
+
+#[test]
+fn reader_resyncs_after_dropped_byte() {
+    let mut reader = FrameReader::new();
+    let mut data = frame(&chal());
+    data.remove(20);
+    // The short frame swallows the start of the next one
+    data.extend(frame(&chal()));
+    data.extend(frame(&Message::UnlockGood));
+    let results = read_all(&mut reader, &data);
+    assert_eq!(results[0], Err(FrameError::BadCrc));
+    assert!(results.contains(&Ok(chal())));
+    assert_eq!(results.last(), Some(&Ok(Message::UnlockGood)));
+}
+
+#[test]
+fn good_frame_inside_a_bad_one_is_not_held_back() {
+    let mut reader = FrameReader::new();
+    // The start of a long frame, then a whole short one that the long frame
+    // swallows, then the rest of the long frame's length without another SOF
+    let mut data = frame(&chal())[..30].to_vec();
+    data.extend(frame(&Message::UnlockGood));
+    data.resize(chal().frame_len(), 0x00);
+    // Only the last byte finishes the long frame, and no more follow
+    let (last, rest) = data.split_last().unwrap();
+    assert_eq!(read_all(&mut reader, rest), []);
+    assert_eq!(reader.push(*last), Some(Ok(Message::UnlockGood)));
+    assert_eq!(read_all(&mut reader, &frame(&Message::UnlockRst)), [Ok(Message::UnlockRst)]);
+}
+
+#[test]
+fn length_must_match_message_type() {
+    let mut reader = FrameReader::new();
+    assert_eq!(read_all(&mut reader, &[SOF, 0, 2, 0x41]), [Err(FrameError::BadLength { magic: 0x41, len: 2 })]);
+    assert_eq!(read_all(&mut reader, &[SOF, 0, 0, 0x99]), [Err(FrameError::Decode(DecodeError::BadMagic(0x99)))]);
+    assert_eq!(read_all(&mut reader, &frame(&Message::PairRst)), [Ok(Message::PairRst)]);
+}
+
+#[test]
+fn reader_drops_frames_that_stall() {
+    let mut reader = FrameReader::new();
+    let data = frame(&chal());
+    for byte in &data[..10] {
+        assert_eq!(reader.push_at(*byte, 0), None);
+    }
+    assert!(reader.expire(500_000));
+    assert_eq!(read_all(&mut reader, &frame(&Message::UnlockGood)), [Ok(Message::UnlockGood)]);
+}
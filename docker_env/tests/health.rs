@@ -0,0 +1,82 @@
+#![cfg(feature = "std")]
+
+use tiva::{
+    get_timer_entropy,
+    health::{HealthFailure, HealthTests, TEMP_APT_WINDOW, TEMP_RCT_CUTOFF},
+    host::VirtualClock,
+    Clock,
+};
+
+/// A tick timer that moves on by a varying amount every read.
+struct JitterClock {
+    ticks: u64,
+    state: u32,
+}
+
+impl Clock for JitterClock {
+    fn sleep_us(&mut self, _us: u32) {}
+    fn start_delay_timer_us(&mut self, _us: u32) {}
+    fn wait_delay_timer(&mut self) {}
+    fn get_remaining_us_delay_timer(&mut self) -> u32 {
+        0
+    }
+    fn get_tick_timer(&mut self) -> u64 {
+        self.state = self.state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        self.ticks += 100 + (self.state >> 24) as u64;
+        self.ticks
+    }
+}
+
+fn run(tests: &mut HealthTests, samples: impl IntoIterator<Item = u8>) -> Result<(), HealthFailure> {
+    for sample in samples {
+        tests.sample(sample);
+    }
+    tests.result()
+}
+
+#[test]
+fn varying_samples_pass() {
+    let mut tests = HealthTests::temp();
+    let samples = (0..8192u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8);
+    assert_eq!(run(&mut tests, samples), Ok(()));
+}
+
+#[test]
+fn stuck_source_fails_the_repetition_count() {
+    let mut tests = HealthTests::temp();
+    assert_eq!(run(&mut tests, [7; TEMP_RCT_CUTOFF as usize - 1]), Ok(()));
+    assert_eq!(run(&mut tests, [7]), Err(HealthFailure::RepetitionCount));
+    // A failed source stays failed
+    assert_eq!(run(&mut tests, [1, 2, 3]), Err(HealthFailure::RepetitionCount));
+}
+
+#[test]
+fn lopsided_source_fails_the_adaptive_proportion() {
+    // Half one value is fine at 0.5 bits a sample, nine in ten is not
+    let mut tests = HealthTests::temp();
+    let samples = (0..TEMP_APT_WINDOW).map(|i| if i % 8 == 7 { i as u8 } else if i % 2 == 0 { 5 } else { 5 ^ 1 });
+    assert_eq!(run(&mut tests, samples), Ok(()));
+    let mut tests = HealthTests::temp();
+    let samples = (0..TEMP_APT_WINDOW).map(|i| if i % 10 == 9 { i as u8 | 0x80 } else { 5 });
+    assert_eq!(run(&mut tests, samples), Err(HealthFailure::AdaptiveProportion));
+}
+
+#[test]
+fn adaptive_proportion_starts_over_every_window() {
+    // About 300 of every 512 is under the cutoff, however many windows there are
+    let mut tests = HealthTests::temp();
+    let samples = (0..8 * TEMP_APT_WINDOW).map(|i| {
+        let i = i % TEMP_APT_WINDOW;
+        if i % 5 < 3 { 9 } else { i as u8 | 0x10 }
+    });
+    assert_eq!(run(&mut tests, samples), Ok(()));
+}
+
+#[test]
+fn timer_entropy_needs_a_moving_timer() {
+    // The virtual clock only moves when told to, like a stuck timer
+    assert_eq!(get_timer_entropy(&mut VirtualClock::new()), Err(HealthFailure::RepetitionCount));
+    let mut clock = JitterClock { ticks: 0, state: 1 };
+    let first = get_timer_entropy(&mut clock).unwrap();
+    assert_ne!(get_timer_entropy(&mut clock).unwrap(), first);
+}
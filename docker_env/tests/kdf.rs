@@ -0,0 +1,65 @@
+#![cfg(feature = "std")]
+
+use tiva::kdf::{hmac_sha256, pbkdf2_sha256, HmacSha256, Pbkdf2};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+// RFC 4231 test cases 1, 2 and 6
+#[test]
+fn hmac_matches_rfc() {
+    assert_eq!(
+        hmac_sha256(&[0x0B; 20], b"Hi There").to_vec(),
+        hex("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+    );
+    assert_eq!(
+        hmac_sha256(b"Jefe", b"what do ya want for nothing?").to_vec(),
+        hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+    );
+    assert_eq!(
+        hmac_sha256(&[0xAA; 131], b"Test Using Larger Than Block-Size Key - Hash Key First").to_vec(),
+        hex("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54")
+    );
+}
+
+#[test]
+fn hmac_can_be_fed_in_pieces() {
+    let mut mac = HmacSha256::new(b"Jefe");
+    mac.update(b"what do ya want ");
+    mac.update(b"for nothing?");
+    assert_eq!(mac.finish(), hmac_sha256(b"Jefe", b"what do ya want for nothing?"));
+}
+
+// The PBKDF2-HMAC-SHA256 vectors everyone uses, after RFC 6070
+#[test]
+fn pbkdf2_matches_known_vectors() {
+    let cases = [
+        (1, "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"),
+        (2, "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"),
+        (4096, "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"),
+    ];
+    for (iterations, expected) in cases {
+        let mut out = [0u8; 32];
+        pbkdf2_sha256(b"password", b"salt", iterations, &mut out);
+        assert_eq!(out.to_vec(), hex(expected));
+    }
+
+    // Longer than one HMAC output
+    let mut out = [0u8; 40];
+    pbkdf2_sha256(b"passwordPASSWORDpassword", b"saltSALTsaltSALTsaltSALTsaltSALTsalt", 4096, &mut out);
+    assert_eq!(out.to_vec(), hex("348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1c635518c7dac47e9"));
+}
+
+// The fob's main loop counts on a step running no more than it is asked to
+#[test]
+fn pbkdf2_steps_run_exactly_the_iterations_asked_for() {
+    // The first iteration is done up front
+    let mut pbkdf2 = Pbkdf2::new(b"password", b"salt", 4096);
+    assert!(!pbkdf2.step(4000));
+    assert!(!pbkdf2.step(94));
+    assert!(pbkdf2.step(1));
+    assert!(pbkdf2.step(1));
+    let expected = hex("c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a");
+    assert_eq!(pbkdf2.finish().to_vec(), expected);
+}
@@ -0,0 +1,177 @@
+#![cfg(feature = "std")]
+
+use tiva::{
+    frame::MAX_FRAME_LEN,
+    host::{channel_pair, Channel},
+    link::{Link, LinkError, MAX_TRIES, US_ACK_TIMEOUT},
+    protocol::{Message, LEN_CHUNK, LEN_PAIR_SECRETS},
+    Transport,
+};
+
+fn pair_fin() -> Message {
+    Message::PairFin { sealed: [1; LEN_PAIR_SECRETS], tag: [2; 16] }
+}
+
+fn drain(channel: &mut Channel) -> Vec<u8> {
+    let mut data = Vec::new();
+    while channel.avail() {
+        data.push(channel.readb());
+    }
+    data
+}
+
+fn frame(msg: &Message) -> Vec<u8> {
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    let len = msg.encode_frame(&mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+/// Two links with a wire in between that can lose or mangle frames.
+struct Wire {
+    a: Link,
+    b: Link,
+    a_end: Channel,
+    b_end: Channel,
+    now_us: u64,
+}
+
+impl Wire {
+    fn new() -> Wire {
+        let (a_end, b_end) = channel_pair();
+        Wire { a: Link::new(), b: Link::new(), a_end, b_end, now_us: 0 }
+    }
+
+    /// Runs both sides a millisecond at a time until `a` has nothing left to
+    /// send. `mangle` sees every frame from `a` to `b` with its number, and
+    /// may change it. Returns what `b` received.
+    fn run(&mut self, mut mangle: impl FnMut(usize, &mut Vec<u8>)) -> Vec<Message> {
+        let mut received = Vec::new();
+        let mut frames = 0;
+        for _ in 0..10_000 {
+            let mut data = drain(&mut self.b_end);
+            if !data.is_empty() {
+                mangle(frames, &mut data);
+                frames += 1;
+            }
+            for byte in data {
+                received.extend(self.b.on_byte(byte, self.now_us, &mut self.b_end));
+            }
+            for byte in drain(&mut self.a_end) {
+                self.a.on_byte(byte, self.now_us, &mut self.a_end);
+            }
+            self.a.on_tick(self.now_us, &mut self.a_end);
+            self.b.on_tick(self.now_us, &mut self.b_end);
+            if self.a.idle() {
+                return received;
+            }
+            self.now_us += 1_000;
+        }
+        panic!("link never went idle");
+    }
+}
+
+#[test]
+fn long_messages_go_in_chunks() {
+    let mut wire = Wire::new();
+    wire.a.send(&pair_fin(), &mut wire.a_end).unwrap();
+    wire.a.send(&Message::UnlockGood, &mut wire.a_end).unwrap();
+
+    // Only one chunk is in flight at a time
+    let first = drain(&mut wire.b_end);
+    let data = frame(&Message::LinkData { seq: 0, restart: true, len: LEN_CHUNK as u8, data: [0x43, 1, 1, 1, 1, 1] });
+    assert_eq!(first, data);
+    for byte in first {
+        assert_eq!(wire.b.on_byte(byte, 0, &mut wire.b_end), None);
+    }
+
+    let mut chunks = 1;
+    let received = wire.run(|_, _| chunks += 1);
+    assert_eq!(received, [pair_fin(), Message::UnlockGood]);
+    assert_eq!(chunks, pair_fin().encoded_len().div_ceil(LEN_CHUNK) + 1);
+}
+
+#[test]
+fn lost_and_mangled_chunks_are_sent_again() {
+    let mut wire = Wire::new();
+    wire.a.send(&pair_fin(), &mut wire.a_end).unwrap();
+    let received = wire.run(|n, data| match n {
+        3 => data.clear(),
+        7 => data[6] ^= 0x01,
+        _ => {}
+    });
+    assert_eq!(received, [pair_fin()]);
+    assert!(wire.now_us >= 2 * US_ACK_TIMEOUT);
+}
+
+#[test]
+fn duplicate_chunks_are_acknowledged_but_dropped() {
+    let (mut a_end, mut b_end) = channel_pair();
+    let mut b = Link::new();
+    let data = frame(&Message::LinkData { seq: 9, restart: true, len: 1, data: [0x63, 0, 0, 0, 0, 0] });
+    a_end.write(&data);
+    a_end.write(&data);
+
+    let received: Vec<_> = drain(&mut b_end).into_iter().filter_map(|byte| b.on_byte(byte, 0, &mut b_end)).collect();
+    assert_eq!(received, [Message::UnlockGood]);
+    let mut acks = frame(&Message::LinkAck { seq: 9 });
+    acks.extend(frame(&Message::LinkAck { seq: 9 }));
+    assert_eq!(drain(&mut a_end), acks);
+}
+
+#[test]
+fn sender_gives_up_and_restarts() {
+    let mut wire = Wire::new();
+    wire.a.send(&pair_fin(), &mut wire.a_end).unwrap();
+    // The other side hears the first two chunks, then nothing at all
+    let received = wire.run(|n, data| {
+        if n >= 2 {
+            data.clear();
+        }
+    });
+    assert!(received.is_empty());
+    assert!(wire.now_us >= MAX_TRIES as u64 * US_ACK_TIMEOUT);
+
+    // The next message starts over, and the half received one is dropped
+    wire.a.send(&Message::UnlockRst, &mut wire.a_end).unwrap();
+    assert_eq!(wire.run(|_, _| {}), [Message::UnlockRst]);
+}
+
+#[test]
+fn reset_drops_everything_queued() {
+    let mut wire = Wire::new();
+    wire.a.send(&pair_fin(), &mut wire.a_end).unwrap();
+    wire.a.reset();
+    assert!(wire.a.idle());
+    drain(&mut wire.b_end);
+
+    wire.a.send(&Message::PairRst, &mut wire.a_end).unwrap();
+    assert_eq!(wire.run(|_, _| {}), [Message::PairRst]);
+}
+
+#[test]
+fn queue_has_room_for_two_long_messages() {
+    let (mut a_end, _b_end) = channel_pair();
+    let mut a = Link::new();
+    a.send(&pair_fin(), &mut a_end).unwrap();
+    a.send(&pair_fin(), &mut a_end).unwrap();
+    assert_eq!(a.send(&Message::UnlockGood, &mut a_end), Err(LinkError::QueueFull));
+}
+
+#[test]
+fn rebooted_sender_is_heard() {
+    let mut wire = Wire::new();
+    wire.a.send(&Message::UnlockReq, &mut wire.a_end).unwrap();
+    assert_eq!(wire.run(|_, _| {}), [Message::UnlockReq]);
+
+    // The fob resets and starts over at the same sequence number. A long
+    // while later SW1 is pressed again
+    wire.a = Link::new();
+    wire.now_us += 2_000_000;
+    wire.a.send(&Message::UnlockReq, &mut wire.a_end).unwrap();
+    assert_eq!(wire.run(|_, _| {}), [Message::UnlockReq]);
+
+    // Or right away, with something else to say
+    wire.a = Link::new();
+    wire.a.send(&Message::UnlockRst, &mut wire.a_end).unwrap();
+    assert_eq!(wire.run(|_, _| {}), [Message::UnlockRst]);
+}
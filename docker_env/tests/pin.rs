@@ -0,0 +1,83 @@
+#![cfg(feature = "std")]
+
+use tiva::kdf::{hmac_sha256, pbkdf2_sha256};
+use tiva::pin::{
+    calibrate_iterations, pin_hash, PinHash, CALIBRATION_ITERATIONS, MAX_PIN_HASH_ITERATIONS, MIN_PIN_HASH_ITERATIONS,
+    PIN_HASH_ITERATIONS,
+};
+use tiva::{clock::TICKS_PER_US, host::VirtualClock, Clock};
+
+const PIN: [u8; 3] = [0x12, 0x34, 0x56];
+const SALT: [u8; 12] = [0x5A; 12];
+
+// Plain PBKDF2 and HMAC underneath, e.g. Python's hashlib.pbkdf2_hmac and
+// hmac give the same
+#[test]
+fn hash_is_labelled_pbkdf2() {
+    let mut expected = [0u8; 32];
+    pbkdf2_sha256(&PIN, &[b"PwnyPARED PIN hash".as_slice(), &SALT].concat(), 4096, &mut expected);
+    assert_eq!(expected[..4], [0x03, 0x37, 0x9F, 0xB7]);
+    let keys = pin_hash(&PIN, &SALT, 4096);
+    assert_eq!(keys.check, hmac_sha256(&expected, b"PwnyPARED PIN check"));
+    assert_eq!(keys.wrap_key, hmac_sha256(&expected, b"PwnyPARED wrap key"));
+}
+
+#[test]
+fn hash_in_steps_matches_hash_in_one_go() {
+    let mut hash = PinHash::new(&PIN, &SALT, PIN_HASH_ITERATIONS);
+    let mut steps = 0;
+    while !hash.step() {
+        steps += 1;
+    }
+    assert!(steps > 1);
+    let keys = hash.finish();
+    let expected = pin_hash(&PIN, &SALT, PIN_HASH_ITERATIONS);
+    assert_eq!(keys.check, expected.check);
+    assert_eq!(keys.wrap_key, expected.wrap_key);
+}
+
+#[test]
+fn iteration_count_is_capped() {
+    let check = |iterations| pin_hash(&PIN, &SALT, iterations).check;
+    assert_eq!(check(u32::MAX), check(MAX_PIN_HASH_ITERATIONS));
+    assert_eq!(check(0), check(1));
+    assert_ne!(check(1), check(2));
+}
+
+/// A tick timer that moves on by the same amount every read.
+struct StepClock {
+    ticks: u64,
+    step: u64,
+}
+
+impl Clock for StepClock {
+    fn sleep_us(&mut self, _us: u32) {}
+    fn start_delay_timer_us(&mut self, _us: u32) {}
+    fn wait_delay_timer(&mut self) {}
+    fn get_remaining_us_delay_timer(&mut self) -> u32 {
+        0
+    }
+    fn get_tick_timer(&mut self) -> u64 {
+        self.ticks += self.step;
+        self.ticks
+    }
+}
+
+fn calibrate(us_per_iteration: u64) -> u32 {
+    let step = us_per_iteration * CALIBRATION_ITERATIONS as u64 * TICKS_PER_US;
+    calibrate_iterations(&mut StepClock { ticks: 0, step })
+}
+
+#[test]
+fn calibration_fits_the_hash_in_400ms() {
+    assert_eq!(calibrate(100), 4000);
+    assert_eq!(calibrate(50), 8000);
+    // Too slow to be worth hashing in time, or too fast to cap
+    assert_eq!(calibrate(1_000), MIN_PIN_HASH_ITERATIONS);
+    assert_eq!(calibrate(1), MAX_PIN_HASH_ITERATIONS);
+}
+
+#[test]
+fn calibration_without_a_moving_timer_gives_the_default() {
+    assert_eq!(calibrate_iterations(&mut VirtualClock::new()), PIN_HASH_ITERATIONS);
+}
@@ -0,0 +1,182 @@
+#![cfg(feature = "std")]
+
+use p256_cortex_m4::SecretKey;
+use tiva::protocol::{
+    chal_transcript, open_pin, pair_key, resp_transcript, seal_pin, DecodeError, EncodeError, Message, MsgReader,
+    PairSecrets, LEN_PAIR_SECRETS, MAX_MSG_LEN, US_MSG_TIMEOUT,
+};
+
+fn encode(msg: &Message) -> Vec<u8> {
+    let mut buf = [0u8; MAX_MSG_LEN];
+    let len = msg.encode(&mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+#[test]
+fn messages_survive_a_round_trip() {
+    let msgs = [
+        Message::PairReq { pin: [0x12, 0x34, 0x56] },
+        Message::PairSyn { eph_public: [1; 64] },
+        Message::PairAck { eph_public: [2; 64] },
+        Message::PairPin { sealed: [0x12, 0x34, 0x56], tag: [3; 16] },
+        Message::PairFin { sealed: [3; LEN_PAIR_SECRETS], tag: [4; 16] },
+        Message::PairRst,
+        Message::EnabFeat { car_id: [0, 0, 0, 7], feature_num: [0, 0, 0, 2], feature_sig: [6; 64] },
+        Message::UnlockReq,
+        Message::UnlockPing { ping: [0x11; 4] },
+        Message::UnlockPong { pong: [0x22; 4] },
+        Message::UnlockChal { nonce: [7; 16], nonce_sig: [8; 64] },
+        Message::UnlockResp { nonce: [9; 16], nonce_sig: [10; 64] },
+        Message::UnlockGood,
+        Message::UnlockFeat { feature_sigs: [[11; 64], [12; 64], [13; 64]] },
+        Message::UnlockRst,
+        Message::LinkData { seq: 200, restart: true, len: 3, data: [14, 15, 16, 0, 0, 0] },
+        Message::LinkAck { seq: 200 },
+        Message::Calibrate,
+        Message::HostSuccess,
+        Message::HostFailure,
+        Message::HostLocked { ms_left: [0, 0, 0x27, 0x10] },
+        Message::HostReplay { chal_counter: [0, 0, 0, 0x40] },
+        Message::HostCalibrated { rtt_limit: [0, 0, 0x5D, 0xC0] },
+    ];
+    for msg in msgs {
+        let data = encode(&msg);
+        assert_eq!(data.len(), msg.encoded_len());
+        assert_eq!(data[0], msg.magic());
+        assert_eq!(Message::decode(&data), Ok(msg));
+    }
+}
+
+#[test]
+fn layout_matches_the_host_tools() {
+    let data = encode(&Message::PairReq { pin: [0x12, 0x34, 0x56] });
+    assert_eq!(data, [0x40, 0x12, 0x34, 0x56]);
+
+    // package_tool writes car ID, feature number and signature after 0x50
+    let data = encode(&Message::EnabFeat { car_id: [0, 0, 0, 7], feature_num: [0, 0, 0, 2], feature_sig: [6; 64] });
+    assert_eq!(data.len(), 73);
+    assert_eq!(data[..9], [0x50, 0, 0, 0, 7, 0, 0, 0, 2]);
+    assert_eq!(encode(&Message::HostSuccess), [0xAA]);
+
+    let data = encode(&Message::LinkData { seq: 5, restart: true, len: 2, data: [1, 2, 0, 0, 0, 0] });
+    assert_eq!(data, [0x70, 5, 0x82, 1, 2, 0, 0, 0, 0]);
+}
+
+#[test]
+fn decode_rejects_malformed_messages() {
+    assert_eq!(Message::decode(&[]), Err(DecodeError::Empty));
+    assert_eq!(Message::decode(&[0x99]), Err(DecodeError::BadMagic(0x99)));
+    assert_eq!(Message::decode(&[0x40, 1, 2]), Err(DecodeError::Short { expected: 4, actual: 3 }));
+    assert_eq!(Message::decode(&[0x60, 0]), Err(DecodeError::Long { expected: 1, actual: 2 }));
+}
+
+#[test]
+fn encode_needs_room_for_the_whole_message() {
+    let mut buf = [0u8; 8];
+    let msg = Message::UnlockChal { nonce: [7; 16], nonce_sig: [8; 64] };
+    assert_eq!(msg.encode(&mut buf), Err(EncodeError::BufferTooSmall { needed: 81 }));
+    assert_eq!(Message::UnlockGood.encode(&mut buf), Ok(1));
+}
+
+#[test]
+fn reader_puts_messages_back_together() {
+    let mut reader = MsgReader::new();
+    let msg = Message::UnlockResp { nonce: [9; 16], nonce_sig: [10; 64] };
+    let data = encode(&msg);
+    for byte in &data[..data.len() - 1] {
+        assert_eq!(reader.push(*byte), None);
+    }
+    assert_eq!(reader.push(data[data.len() - 1]), Some(Ok(msg)));
+
+    assert_eq!(reader.push(0x99), Some(Err(DecodeError::BadMagic(0x99))));
+    assert_eq!(reader.push(0x63), Some(Ok(Message::UnlockGood)));
+
+    // A dropped message does not swallow the next one
+    reader.push(0x40);
+    reader.reset();
+    assert_eq!(reader.push(0x44), Some(Ok(Message::PairRst)));
+}
+
+#[test]
+fn reader_drops_messages_that_stall() {
+    let mut reader = MsgReader::new();
+    assert_eq!(reader.push_at(0x40, 1_000), None);
+    assert_eq!(reader.push_at(0x12, 2_000), None);
+    assert!(!reader.expire(1_000 + US_MSG_TIMEOUT - 1));
+    assert!(reader.expire(1_000 + US_MSG_TIMEOUT));
+    assert!(!reader.expire(1_000 + US_MSG_TIMEOUT));
+    assert_eq!(reader.push_at(0x63, 600_000), Some(Ok(Message::UnlockGood)));
+}
+
+fn public(secret: &SecretKey) -> [u8; 64] {
+    secret.public_key().to_untagged_bytes()
+}
+
+#[test]
+fn both_fobs_derive_the_same_pair_key() {
+    let syn_secret = SecretKey::from_bytes([0x55; 32]).unwrap();
+    let ack_secret = SecretKey::from_bytes([0x66; 32]).unwrap();
+    let (syn_public, ack_public) = (public(&syn_secret), public(&ack_secret));
+
+    let key = pair_key(&syn_secret, &ack_public, &syn_public, &ack_public).unwrap();
+    assert_eq!(pair_key(&ack_secret, &syn_public, &syn_public, &ack_public), Some(key));
+    assert_ne!(pair_key(&ack_secret, &syn_public, &ack_public, &syn_public), Some(key));
+    assert_eq!(pair_key(&ack_secret, &[0xFF; 64], &syn_public, &ack_public), None);
+}
+
+#[test]
+fn pair_secrets_only_open_with_the_same_key() {
+    let secrets = PairSecrets {
+        fob_secret: [1; 32],
+        car_id: [0, 0, 0, 7],
+        feature_sigs: [[2; 64], [3; 64], [4; 64]],
+        car_public: [5; 64],
+        next_chal: [0, 0, 1, 0],
+    };
+    let Message::PairFin { sealed, tag } = secrets.seal(&[9; 32]) else { panic!() };
+    assert!(!sealed.windows(32).any(|w| w == [1; 32]));
+    assert_eq!(PairSecrets::open(&sealed, &tag, &[9; 32]), Some(secrets));
+    assert_eq!(PairSecrets::open(&sealed, &tag, &[8; 32]), None);
+
+    let mut changed = sealed;
+    changed[LEN_PAIR_SECRETS - 1] ^= 1;
+    assert_eq!(PairSecrets::open(&changed, &tag, &[9; 32]), None);
+}
+
+#[test]
+fn pin_only_opens_with_the_same_key() {
+    let pin = [0x12, 0x34, 0x56];
+    let Message::PairPin { sealed, tag } = seal_pin(&pin, &[9; 32]) else { panic!() };
+    assert_ne!(sealed, pin);
+    assert_eq!(open_pin(&sealed, &tag, &[9; 32]), Some(pin));
+    assert_eq!(open_pin(&sealed, &tag, &[8; 32]), None);
+    assert_eq!(open_pin(&[sealed[0] ^ 1, sealed[1], sealed[2]], &tag, &[9; 32]), None);
+}
+
+#[test]
+fn unlock_transcripts_cover_every_message() {
+    let car_id = [0, 0, 0, 7];
+    let ping = encode(&Message::UnlockPing { ping: [5; 4] });
+    let pong = encode(&Message::UnlockPong { pong: [6; 4] });
+    let chal = chal_transcript(&car_id, &[5; 4], &[6; 4], &[1; 16]);
+    let mut expected = b"PwnyPARED UNLOCK_CHAL".to_vec();
+    expected.extend([0, 0, 0, 7, 0x60]);
+    expected.extend(&ping);
+    expected.extend(&pong);
+    expected.extend(encode(&Message::UnlockChal { nonce: [1; 16], nonce_sig: [2; 64] })[..17].iter());
+    assert_eq!(chal[..], expected[..]);
+
+    let resp = resp_transcript(&car_id, &[5; 4], &[6; 4], &[1; 16], &[2; 64], &[3; 16]);
+    let mut expected = b"PwnyPARED UNLOCK_RESP".to_vec();
+    expected.extend([0, 0, 0, 7, 0x60]);
+    expected.extend(&ping);
+    expected.extend(&pong);
+    expected.extend(encode(&Message::UnlockChal { nonce: [1; 16], nonce_sig: [2; 64] }));
+    expected.extend(encode(&Message::UnlockResp { nonce: [3; 16], nonce_sig: [4; 64] })[..17].iter());
+    assert_eq!(resp[..], expected[..]);
+
+    // Nothing signed for one car or step passes for another
+    assert_ne!(resp_transcript(&[0, 0, 0, 8], &[5; 4], &[6; 4], &[1; 16], &[2; 64], &[3; 16]), resp);
+    assert_ne!(resp_transcript(&car_id, &[5; 4], &[7; 4], &[1; 16], &[2; 64], &[3; 16]), resp);
+    assert_ne!(chal[..], resp[..chal.len()]);
+}
@@ -0,0 +1,83 @@
+#![cfg(feature = "std")]
+
+use p256_cortex_m4::{PublicKey, SecretKey, Signature};
+use rand_chacha::rand_core::RngCore;
+use tiva::{rfc6979::Rfc6979, sha256, Signer, Verifier};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+// RFC 6979 appendix A.2.5, P-256 with SHA-256
+const SECRET: &str = "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721";
+const PUBLIC: &str = "60fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb6\
+                      7903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299";
+const VECTORS: [(&[u8], &str, &str, &str); 2] = [
+    (
+        b"sample",
+        "a6e3c57dd01abe90086538398355dd4c3b17aa873382b0f24d6129493d8aad60",
+        "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716",
+        "f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8",
+    ),
+    (
+        b"test",
+        "d16b6ae827f17175e040871a1c7ec3500192c4c92677336ec2537acaee0008e0",
+        "f1abb023518351cd71d881567b1ea663ed3efcf6c5132b354f28d3b0b7d38367",
+        "019f4113742a2b14bd25926b49c649155f267e60d3814b4c0cc84250e46f0083",
+    ),
+];
+
+fn secret() -> SecretKey {
+    SecretKey::from_bytes(hex(SECRET)).unwrap()
+}
+
+#[test]
+fn nonces_match_rfc() {
+    let x: [u8; 32] = hex(SECRET).try_into().unwrap();
+    for (message, k, _, _) in VECTORS {
+        let mut nonces = Rfc6979::new(&x, &sha256(message), &[]);
+        assert_eq!(nonces.next_k().to_vec(), hex(k));
+    }
+}
+
+#[test]
+fn signatures_match_rfc() {
+    let public = PublicKey::from_untagged_bytes(&hex(PUBLIC)).unwrap();
+    assert_eq!(secret().public_key().to_untagged_bytes().to_vec(), hex(PUBLIC));
+    for (message, _, r, s) in VECTORS {
+        let sig = secret().sign_rfc6979(message, &[]);
+        assert_eq!(sig.to_untagged_bytes().to_vec(), [hex(r), hex(s)].concat());
+        assert!(public.verify(message, &sig));
+    }
+}
+
+#[test]
+fn hedged_signatures_depend_on_the_extra_bytes() {
+    let public = secret().public_key();
+    let plain = secret().sign_rfc6979(b"sample", &[]).to_untagged_bytes();
+    let hedged = secret().sign_rfc6979(b"sample", &[1; 32]).to_untagged_bytes();
+    let other = secret().sign_rfc6979(b"sample", &[2; 32]).to_untagged_bytes();
+    assert_ne!(hedged, plain);
+    assert_ne!(hedged, other);
+    assert_eq!(secret().sign_rfc6979(b"sample", &[1; 32]).to_untagged_bytes(), hedged);
+    for sig in [hedged, other] {
+        let sig = Signature::from_untagged_bytes(&sig).unwrap();
+        assert!(public.verify(b"sample", &sig));
+    }
+}
+
+#[test]
+fn candidates_come_out_as_random_bytes_in_order() {
+    let x: [u8; 32] = hex(SECRET).try_into().unwrap();
+    let mut nonces = Rfc6979::new(&x, &sha256(b"sample"), &[]);
+    let first = nonces.next_k();
+    let second = nonces.next_k();
+    assert_ne!(first, second);
+    // The signer asks for random bytes and gets the same candidates, big
+    // endian for the fallback P-256 code used off the board
+    let mut again = Rfc6979::new(&x, &sha256(b"sample"), &[]);
+    let mut bytes = [0u8; 64];
+    again.fill_bytes(&mut bytes);
+    assert_eq!(bytes[..32], first);
+    assert_eq!(bytes[32..], second);
+}
@@ -0,0 +1,170 @@
+#![cfg(feature = "std")]
+
+use rand_chacha::rand_core::RngCore;
+use tiva::{
+    rng::{next_rng_seed, EventRng, HmacDrbg, LEN_ENTROPY, LEN_RNG_SEED, POOL_RESEED_BITS, RESEED_INTERVAL},
+    EntropyPool, Event, Port, EVENT_ENTROPY_BUTTON,
+};
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+/// Hands out 0, 1, 2, ... in every byte and counts how often it was asked.
+fn counting_source() -> impl FnMut(&mut EntropyPool) -> [u8; LEN_ENTROPY] {
+    let mut calls = 0u8;
+    move |_| {
+        calls += 1;
+        [calls - 1; LEN_ENTROPY]
+    }
+}
+
+// NIST CAVP HMAC_DRBG SHA-256, no reseed, no personalization or additional
+// input, first vector
+#[test]
+fn output_matches_nist_vector() {
+    let mut drbg = HmacDrbg::instantiate(
+        counting_source(),
+        &hex("ca851911349384bffe89de1cbdc46e6831e44d34a4fb935ee285dd14b71a7488"),
+        &hex("659ba96c601dc69fc902940805ec0ca8"),
+        &[],
+    );
+    let mut out = [0u8; 128];
+    drbg.generate(&mut out, &[]);
+    drbg.generate(&mut out, &[]);
+    assert_eq!(
+        out.to_vec(),
+        hex("e528e9abf2dece54d47c7e75e5fe302149f817ea9fb4bee6f4199697d04d5b89\
+             d54fbb978a15b5c443c9ec21036d2460b6f73ebad0dc2aba6e624abf07745bc1\
+             07694bb7547bb0995f70de25d6b29e2d3011bb19d27676c07162c8b5ccde0668\
+             961df86803482cb37ed6d5c0bb8d50cf1f50d476aa0458bdaba806f48be9dcb8")
+    );
+}
+
+#[test]
+fn personalization_keeps_generators_apart() {
+    let mut car = HmacDrbg::new(counting_source(), b"car");
+    let mut fob = HmacDrbg::new(counting_source(), b"fob");
+    assert_ne!(car.next_u64(), fob.next_u64());
+}
+
+/// Requests made with a source that counts how often it was asked.
+fn calls_after(requests: u32) -> u32 {
+    let mut calls = 0;
+    {
+        let mut drbg = HmacDrbg::new(
+            |_: &mut EntropyPool| {
+                calls += 1;
+                [7; LEN_ENTROPY]
+            },
+            &[],
+        );
+        for _ in 0..requests {
+            drbg.next_u32();
+        }
+    }
+    calls
+}
+
+#[test]
+fn reseeds_from_the_source_on_a_counter() {
+    // Entropy and nonce, then nothing until the interval is up
+    assert_eq!(calls_after(RESEED_INTERVAL), 2);
+    assert_eq!(calls_after(RESEED_INTERVAL + 1), 3);
+    assert_eq!(calls_after(2 * RESEED_INTERVAL + 1), 4);
+}
+
+/// SW1 presses that add up to just under `POOL_RESEED_BITS`.
+const PRESSES: u64 = (POOL_RESEED_BITS * 8 / EVENT_ENTROPY_BUTTON - 1) as u64;
+
+#[test]
+fn events_wait_for_a_reseed() {
+    let mut quiet = HmacDrbg::new(counting_source(), &[]);
+    let mut pressed = HmacDrbg::new(counting_source(), &[]);
+    for i in 0..PRESSES {
+        pressed.add_event(Event::Button, 1_000_000 * i);
+    }
+    assert_eq!(pressed.pool_bits(), POOL_RESEED_BITS - EVENT_ENTROPY_BUTTON / 8);
+    assert_eq!(pressed.next_u64(), quiet.next_u64());
+    quiet.reseed(&[]);
+    pressed.reseed(&[]);
+    assert_eq!(pressed.pool_bits(), 0);
+    assert_ne!(pressed.next_u64(), quiet.next_u64());
+}
+
+#[test]
+fn full_pool_reseeds_early() {
+    let mut calls = 0;
+    {
+        let mut drbg = HmacDrbg::new(
+            |_: &mut EntropyPool| {
+                calls += 1;
+                [7; LEN_ENTROPY]
+            },
+            &[],
+        );
+        for i in 0..=PRESSES {
+            drbg.add_event(Event::Button, 1_000_000 * i);
+        }
+        drbg.next_u32();
+        assert_eq!(drbg.pool_bits(), 0);
+    }
+    assert_eq!(calls, 3);
+}
+
+#[test]
+fn boot_sampling_does_not_fill_the_pool() {
+    // Like get_combined_entropy(): a batch of ADC samples every call
+    let mut calls = 0;
+    {
+        let mut drbg = HmacDrbg::new(
+            |pool: &mut EntropyPool| {
+                calls += 1;
+                for i in 0..1024 {
+                    pool.add(Event::AdcSample, i);
+                }
+                [7; LEN_ENTROPY]
+            },
+            &[],
+        );
+        assert_eq!(drbg.pool_bits(), 0);
+        drbg.next_u32();
+    }
+    // Entropy and nonce, and no reseed on the first request
+    assert_eq!(calls, 2);
+}
+
+#[test]
+fn pool_estimates_add_up() {
+    let mut pool = EntropyPool::new();
+    for i in 0..64 {
+        pool.add(Event::UartByte(Port::Host), i);
+    }
+    assert_eq!(pool.entropy_bits(), 8);
+    for i in 0..1000 {
+        pool.add(Event::Button, i);
+    }
+    // The pool cannot hold more than its hash
+    assert_eq!(pool.entropy_bits(), 256);
+    let first = pool.take();
+    assert_eq!(pool.entropy_bits(), 0);
+
+    // Only the times differ
+    let mut other = EntropyPool::new();
+    other.add(Event::AdcSample, 1);
+    let mut again = EntropyPool::new();
+    again.add(Event::AdcSample, 2);
+    assert_ne!(other.take(), again.take());
+    assert_ne!(EntropyPool::new().take(), first);
+}
+
+#[test]
+fn saved_seed_carries_over_between_boots() {
+    // Two boots with the same weak entropy still come up differently
+    let mut first = HmacDrbg::new(|_: &mut EntropyPool| [0; LEN_ENTROPY], &[]);
+    let saved = next_rng_seed(&mut first, &[0xFF; LEN_RNG_SEED]);
+    let mut second = HmacDrbg::new(|_: &mut EntropyPool| [0; LEN_ENTROPY], &[]);
+    let next = next_rng_seed(&mut second, &saved);
+    assert_ne!(next, saved);
+    assert_ne!(first.next_u64(), second.next_u64());
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "std")]
+
+use std::fs;
+
+use tiva::{host::FileStorage, words_to_bytes, bytes_to_words, Storage};
+
+/// Same layout as an unpaired fob from build_fob_eeprom.py
+fn unpaired_fob_image() -> Vec<u8> {
+    let mut image = vec![0xFF; 2048];
+    image[0x140..0x14C].copy_from_slice(&[0x5A; 12]);
+    image[0x400..0x404].copy_from_slice(&[0, 0, 0, 0]);
+    image
+}
+
+#[test]
+fn file_storage_reads_and_persists_eeprom_image() {
+    let path = std::env::temp_dir().join(format!("tiva-storage-{}.bin", std::process::id()));
+    fs::write(&path, unpaired_fob_image()).unwrap();
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    let mut salt_w = [0u32; 3];
+    let mut salt = [0u8; 12];
+    storage.read(&mut salt_w, 0x140);
+    words_to_bytes(&salt_w, &mut salt);
+    assert_eq!(salt, [0x5A; 12]);
+
+    let mut paired_w = [0u32; 1];
+    bytes_to_words(&[0, 0, 0, 1], &mut paired_w);
+    storage.write(&paired_w, 0x400);
+    drop(storage);
+
+    let image = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(image.len(), 2048);
+    assert_eq!(image[0x400..0x404], [0, 0, 0, 1]);
+    assert_eq!(image[0x404], 0xFF);
+}
+
+#[test]
+fn file_storage_rejects_wrong_size() {
+    let path = std::env::temp_dir().join(format!("tiva-storage-short-{}.bin", std::process::id()));
+    fs::write(&path, [0xFF; 1024]).unwrap();
+    assert!(FileStorage::open(&path).is_err());
+    fs::write(&path, [0xFF; 4096]).unwrap();
+    assert!(FileStorage::open(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "std")]
+
+use tiva::{host::channel_pair, Transport};
+
+#[test]
+fn channel_pair_is_connected_both_ways() {
+    let (mut car, mut fob) = channel_pair();
+    assert!(!car.avail());
+    assert!(!fob.avail());
+
+    fob.write(&[0x60]);
+    assert!(car.avail());
+    assert_eq!(car.readb(), 0x60);
+    assert!(!car.avail());
+
+    car.write(&[0x61, 1, 2, 3]);
+    let mut msg = [0u8; 4];
+    fob.read(&mut msg);
+    assert_eq!(msg, [0x61, 1, 2, 3]);
+}
+
+#[test]
+fn read_until_gives_up_on_partial_message() {
+    let (mut car, mut fob) = channel_pair();
+    fob.write(&[1, 2]);
+
+    let mut polls = 0;
+    let mut msg = [0u8; 4];
+    assert!(!car.read_until(&mut msg, || {
+        polls += 1;
+        polls > 10
+    }));
+    assert_eq!(msg[..2], [1, 2]);
+
+    fob.write(&[3, 4]);
+    assert!(car.read_until(&mut msg[2..], || false));
+    assert_eq!(msg, [1, 2, 3, 4]);
+}
@@ -0,0 +1,46 @@
+#![cfg(feature = "std")]
+
+use tiva::wrap::{unwrap, wrap, UnwrapError, LEN_WRAPPED_KEY, WRAP_VERSION};
+
+const SECRET: [u8; 32] = [0x11; 32];
+const KEY: [u8; 32] = [0x5A; 32];
+
+fn record() -> [u8; LEN_WRAPPED_KEY] {
+    wrap(&SECRET, &KEY, &[0xA5; 12])
+}
+
+#[test]
+fn record_unwraps_with_the_same_key() {
+    let record = record();
+    assert_eq!(record[..8], [WRAP_VERSION, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(record[8..20], [0xA5; 12]);
+    assert!(!record.windows(32).any(|w| w == SECRET));
+    assert_eq!(unwrap(&record, &KEY), Ok(SECRET));
+}
+
+#[test]
+fn wrong_key_fails() {
+    let mut key = KEY;
+    key[31] ^= 0x01;
+    assert_eq!(unwrap(&record(), &key), Err(UnwrapError::BadTag));
+}
+
+#[test]
+fn any_change_to_the_record_fails() {
+    let record = record();
+    for i in 0..LEN_WRAPPED_KEY {
+        let mut changed = record;
+        changed[i] ^= 0x01;
+        let err = if i == 0 { UnwrapError::BadVersion(WRAP_VERSION ^ 0x01) } else { UnwrapError::BadTag };
+        assert_eq!(unwrap(&changed, &KEY), Err(err), "byte {}", i);
+    }
+}
+
+#[test]
+fn other_versions_are_refused() {
+    for version in [0, 2, 0xFF] {
+        let mut record = record();
+        record[0] = version;
+        assert_eq!(unwrap(&record, &KEY), Err(UnwrapError::BadVersion(version)));
+    }
+}
@@ -23,6 +23,135 @@
 
 #define TEMP_SAMPLES 8
 
+// Size of each UART ring buffer. Must be a power of two.
+#define UART_BUF_SIZE 256
+
+/**
+ * @brief A ring buffer between a UART interrupt handler and the main program.
+ *
+ * The head is only moved by the side that adds bytes and the tail only by
+ * the side that takes them, so neither needs a lock. The indices run freely
+ * and wrap around, so head - tail is always the number of bytes held.
+ */
+typedef struct {
+  volatile uint8_t data[UART_BUF_SIZE];
+  volatile uint32_t head;
+  volatile uint32_t tail;
+} ring_t;
+
+typedef struct {
+  uint32_t base;
+  uint32_t interrupt;
+  ring_t rx;
+  ring_t tx;
+  // Bytes received while the RX buffer was full, plus hardware FIFO overruns
+  volatile uint32_t rx_overflows;
+} uart_t;
+
+static uart_t host_uart = {.base = HOST_UART, .interrupt = INT_UART0};
+static uart_t board_uart = {.base = BOARD_UART, .interrupt = INT_UART1};
+
+// The bootloader owns the vector table in flash, so our interrupt handlers
+// go into a copy of it in RAM. VTOR needs it aligned to 1024 bytes.
+static void (*ram_vectors[NUM_INTERRUPTS_TM4C123])(void) __attribute__((aligned(1024)));
+
+static inline uint32_t ring_count(ring_t *ring) { return ring->head - ring->tail; }
+
+/**
+ * @brief Point the NVIC at a copy of the current vector table in RAM, so
+ *        handlers can be installed.
+ */
+static void vector_table_init(void) {
+  const uint32_t *table = (const uint32_t *)NVIC_VTABLE_R;
+  for (int i = 0; i < NUM_INTERRUPTS_TM4C123; ++i) {
+    ram_vectors[i] = (void (*)(void))table[i];
+  }
+  NVIC_VTABLE_R = (uint32_t)ram_vectors;
+}
+
+/**
+ * @brief Move bytes from the TX buffer into the UART FIFO while it has room.
+ *
+ * Must not be interrupted by the handler for the same UART.
+ */
+static void uart_tx_pump(uart_t *uart) {
+  while (ring_count(&uart->tx) != 0 && UARTSpaceAvail(uart->base)) {
+    UARTCharPutNonBlocking(uart->base, uart->tx.data[uart->tx.tail % UART_BUF_SIZE]);
+    uart->tx.tail++;
+  }
+}
+
+/**
+ * @brief Interrupt handler shared by both UARTs. Empties the RX FIFO into the
+ *        RX buffer and refills the TX FIFO from the TX buffer.
+ */
+static void uart_isr(uart_t *uart) {
+  UARTIntClear(uart->base, UARTIntStatus(uart->base, true));
+  if (UARTRxErrorGet(uart->base) & UART_RXERROR_OVERRUN) {
+    uart->rx_overflows++;
+  }
+  UARTRxErrorClear(uart->base);
+
+  while (UARTCharsAvail(uart->base)) {
+    uint8_t byte = UARTCharGetNonBlocking(uart->base);
+    if (ring_count(&uart->rx) == UART_BUF_SIZE) {
+      uart->rx_overflows++;
+    } else {
+      uart->rx.data[uart->rx.head % UART_BUF_SIZE] = byte;
+      uart->rx.head++;
+    }
+  }
+
+  uart_tx_pump(uart);
+}
+
+static void uart_host_isr(void) { uart_isr(&host_uart); }
+static void uart_board_isr(void) { uart_isr(&board_uart); }
+
+/**
+ * @brief Install the handler for a UART and turn on its interrupts.
+ *
+ * RX interrupts fire once the FIFO is 1/8 full, or when bytes have sat in it
+ * for a while. TX interrupts fire once it is down to 1/8 full.
+ */
+static void uart_int_init(uart_t *uart, void (*handler)(void)) {
+  ram_vectors[uart->interrupt] = handler;
+  UARTFIFOLevelSet(uart->base, UART_FIFO_TX1_8, UART_FIFO_RX1_8);
+  UARTIntEnable(uart->base, UART_INT_RX | UART_INT_RT | UART_INT_TX | UART_INT_OE);
+  NVIC_EN0_R = 1 << (uart->interrupt - 16);
+}
+
+/**
+ * @brief Take a byte from the RX buffer, waiting for one if it is empty.
+ */
+static int32_t uart_readb(uart_t *uart) {
+  while (ring_count(&uart->rx) == 0) {}
+  uint8_t byte = uart->rx.data[uart->rx.tail % UART_BUF_SIZE];
+  uart->rx.tail++;
+  return byte;
+}
+
+/**
+ * @brief Add a byte to the TX buffer and start sending it.
+ *
+ * If the buffer is full, bytes are pushed out by hand until there is room, so
+ * this also works with interrupts masked.
+ */
+static void uart_writeb(uart_t *uart, uint8_t data) {
+  uint32_t mask = 1 << (uart->interrupt - 16);
+  while (ring_count(&uart->tx) == UART_BUF_SIZE) {
+    NVIC_DIS0_R = mask;
+    uart_tx_pump(uart);
+    NVIC_EN0_R = mask;
+  }
+  uart->tx.data[uart->tx.head % UART_BUF_SIZE] = data;
+  uart->tx.head++;
+
+  NVIC_DIS0_R = mask;
+  uart_tx_pump(uart);
+  NVIC_EN0_R = mask;
+}
+
 /**
  * @brief Initialize the UART interfaces.
  *
@@ -139,14 +268,22 @@ void init_system(void) {
 
   // Initialize board link UART
   setup_board_link();
+
+  // Receive and send in the background from now on
+  vector_table_init();
+  uart_int_init(&host_uart, uart_host_isr);
+  uart_int_init(&board_uart, uart_board_isr);
+  __asm volatile("cpsie i");
 }
 
-bool uart_avail_host(void) { return UARTCharsAvail(HOST_UART); }
-bool uart_avail_board(void) { return UARTCharsAvail(BOARD_UART); }
-int32_t uart_readb_host(void) { return UARTCharGet(HOST_UART); }
-int32_t uart_readb_board(void) { return UARTCharGet(BOARD_UART); }
-void uart_writeb_host(uint8_t data) { UARTCharPut(HOST_UART, data); }
-void uart_writeb_board(uint8_t data) { UARTCharPut(BOARD_UART, data); }
+bool uart_avail_host(void) { return ring_count(&host_uart.rx) != 0; }
+bool uart_avail_board(void) { return ring_count(&board_uart.rx) != 0; }
+int32_t uart_readb_host(void) { return uart_readb(&host_uart); }
+int32_t uart_readb_board(void) { return uart_readb(&board_uart); }
+void uart_writeb_host(uint8_t data) { uart_writeb(&host_uart, data); }
+void uart_writeb_board(uint8_t data) { uart_writeb(&board_uart, data); }
+uint32_t uart_overflows_host(void) { return host_uart.rx_overflows; }
+uint32_t uart_overflows_board(void) { return board_uart.rx_overflows; }
 
 void eeprom_read(uint32_t *data, uint32_t address, uint32_t count) { EEPROMRead(data, address, count); }
 void eeprom_write(uint32_t *data, uint32_t address, uint32_t count) { EEPROMProgram(data, address, count); }
@@ -15,6 +15,8 @@ extern int32_t uart_readb_host(void);
 extern int32_t uart_readb_board(void);
 extern void uart_writeb_host(uint8_t data);
 extern void uart_writeb_board(uint8_t data);
+extern uint32_t uart_overflows_host(void);
+extern uint32_t uart_overflows_board(void);
 extern void eeprom_read(uint32_t *data, uint32_t address, uint32_t count);
 extern void eeprom_write(uint32_t *data, uint32_t address, uint32_t count);
 extern bool read_sw_1(void);
@@ -2,18 +2,25 @@
 
 import sys
 import os
-import hashlib
+import subprocess
 
 from fastecdsa import ecdsa, curve
 
 
+# Hashes the PIN and wraps FOB_SECRET the same way the firmware does
+WRAP_KEY = os.environ.get("WRAP_KEY", "/sigpwny/target/x86_64-unknown-linux-gnu/release/wrap_key")
+
 secrets_dir = sys.argv[1]
 eeprom_file = sys.argv[2]
 
 fob_secret = None
 fob_secret_enc = None
 fob_salt = os.urandom(12) # Generate fob-unique FOB_SALT
+pin_fails = b"\x00\x00\x00\x00" # No wrong PINs yet
+next_chal = b"\x00\x00\x00\x00" # No car challenges answered yet
+rng_seed = os.urandom(32) # Mixed into the RNG on first boot, then replaced
 pin_hash = None
+pin_hash_iters = None
 car_id = None
 car_public = None
 fob_is_paired = b"\x00\x00\x00\x00"
@@ -26,25 +33,27 @@ if len(sys.argv) > 4:
     car_id = int(sys.argv[3]).to_bytes(4, "big")
     pair_pin = bytes.fromhex(sys.argv[4])
 
-    # Generate PIN_HASH
-    m = hashlib.sha256()
-    m.update(fob_salt + b"\x00" + pair_pin)
-    pin_hash = m.digest()
-
-    # Generate FOB_SECRET_ENC
-    m = hashlib.sha256()
-    m.update(pair_pin + b"\x00" + fob_salt)
-    fob_secret_enc = m.digest()
-    fob_secret_enc = bytes(a ^ b for a, b in zip(fob_secret_enc, fob_secret))
+    # Generate PIN_HASH_ITERS, PIN_HASH and FOB_SECRET_ENC
+    pin_hash_iters, pin_hash, fob_secret_enc = [bytes.fromhex(line) for line in subprocess.check_output([
+        WRAP_KEY,
+        "--secret", os.path.join(secrets_dir, "fob_sec"),
+        "--pin", pair_pin.hex(),
+        "--salt", fob_salt.hex(),
+        "--nonce", os.urandom(12).hex(),
+    ]).decode().split()]
 
     # Set fob to paired
     fob_is_paired = b"\x00\x00\x00\x01"
 
 addresses = {
     "FOBMEM_FOB_SECRET":     [0x100, fob_secret],
-    "FOBMEM_FOB_SECRET_ENC": [0x120, fob_secret_enc],
     "FOBMEM_FOB_SALT":       [0x140, fob_salt],
+    "FOBMEM_PIN_FAILS":      [0x14C, pin_fails],
+    "FOBMEM_NEXT_CHAL":      [0x150, next_chal],
+    "FOBMEM_PIN_HASH_ITERS": [0x15C, pin_hash_iters],
     "FOBMEM_PIN_HASH":       [0x160, pin_hash],
+    "FOBMEM_FOB_SECRET_ENC": [0x180, fob_secret_enc],
+    "FOBMEM_RNG_SEED":       [0x1E0, rng_seed],
     "FOBMEM_CAR_ID":         [0x200, car_id],
     "FOBMEM_FEAT_1_SIG":     [0x240, None],
     "FOBMEM_FEAT_2_SIG":     [0x280, None],